@@ -1,3 +1,8 @@
 fn main() {
     slint_build::compile("ui/app-window.slint").expect("Slint build failed");
+
+    // The Windows tray loads its icon from the executable's resources. Does nothing elsewhere.
+    embed_resource::compile("ui/icons/tray.rc", embed_resource::NONE)
+        .manifest_optional()
+        .expect("Failed to embed the tray icon");
 }
@@ -0,0 +1,943 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::dynamic::DynamicWallpaper;
+use crate::image_proc::{self, Anchor, Backdrop, Mode};
+use crate::os_level::{self, Appearance, Insets, MonitorInfo};
+use crate::overlay;
+use crate::remote;
+use crate::schedule::{self, TimeSpec};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(30 * 60);
+const DEFAULT_ASPECT_TOLERANCE: f64 = 0.2;
+const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_QUALITY: u8 = 90;
+
+// The file as written by the user. Values stay strings until validated so that errors can name
+// the key they came from.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub sources: Vec<String>,
+    pub mode: Option<String>,
+    pub backdrop: Option<String>,
+    // Pixels hidden by panels and docks, like "32 0 48 0". Queried from the system without it.
+    pub insets: Option<String>,
+    pub interval: Option<String>,
+    // What the rendered images are written as: png, jpeg, webp or auto
+    pub format: Option<String>,
+    // JPEG quality from 1 to 100
+    pub quality: Option<u8>,
+    // How much of an image, from 0 to 1, may be cropped away for it to count as a good match
+    pub aspect_tolerance: Option<f64>,
+    // Prefer photos taken on this day, or else this week, in earlier years
+    pub on_this_day: Option<bool>,
+    // Rotate through near-duplicate images as if they were one
+    pub dedupe: Option<bool>,
+    // The name of an imported dynamic wallpaper to follow instead of the sources
+    pub dynamic: Option<String>,
+    // Used instead of the sources while the system is in dark mode
+    pub dark_sources: Option<Vec<String>>,
+    // How much, from 0 to 1, to darken the images in dark mode when there are no dark sources
+    pub darken: Option<f64>,
+    // Write color themes matching the image on the first monitor after every change, and a
+    // palette of every monitor's image for its hooks
+    pub palette: Option<bool>,
+    // Commands run after a background was set, written as [[hook]] tables
+    #[serde(default)]
+    pub hook: Vec<HookConfig>,
+    // Text drawn onto the rendered images, written as [[overlay]] tables
+    #[serde(default)]
+    pub overlay: Vec<OverlayConfig>,
+    // Keyed by monitor identity, name or number
+    #[serde(default)]
+    pub monitors: BTreeMap<String, MonitorConfig>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    // Online sources to download images from, written as [[remote]] tables
+    #[serde(default)]
+    pub remote: Vec<RemoteConfig>,
+    // Where the sun rise and set times for the schedule are computed for
+    pub location: Option<Location>,
+    // Sources that take over at certain times of day, written as [[schedule]] tables
+    #[serde(default)]
+    pub schedule: Vec<ScheduleRule>,
+    // Blend into the new image when the schedule switches instead of cutting
+    pub crossfade: Option<bool>,
+    // What the system looked like when the file was loaded, only asked for when something
+    // changes in dark mode and the system doesn't switch to a dark background by itself
+    #[serde(skip)]
+    pub appearance: Option<Appearance>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MonitorConfig {
+    // Only used inside profiles, to tell layouts with the same monitors apart
+    pub resolution: Option<String>,
+    pub sources: Option<Vec<String>>,
+    pub mode: Option<String>,
+    pub backdrop: Option<String>,
+    pub insets: Option<String>,
+    pub aspect_tolerance: Option<f64>,
+    pub on_this_day: Option<bool>,
+    pub dynamic: Option<String>,
+    pub dark_sources: Option<Vec<String>>,
+    pub darken: Option<f64>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteConfig {
+    // One of remote::PROVIDERS
+    pub provider: String,
+    // The feed address, or another server for the built-in providers
+    pub url: Option<String>,
+    // Only used by apod, the public demo key is used without it
+    pub api_key: Option<String>,
+    // Only used by bing, e.g. "de-DE"
+    pub market: Option<String>,
+    // How many images to download at most per fetch
+    pub count: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+// Use other sources from a time of day on, until the next rule for the monitor starts
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleRule {
+    // "07:30", "sunrise", "sunset", or shifted like "sunset-30m"
+    pub from: String,
+    pub sources: Vec<String>,
+    // Keyed like the monitors table, all monitors without it
+    pub monitors: Option<Vec<String>>,
+}
+
+// A shell command run after a background is set. It gets the monitor and the images in
+// BACKGROUND_* environment variables.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookConfig {
+    pub command: String,
+    // How long it may run before it is stopped, e.g. "10s"
+    pub timeout: Option<String>,
+    // Keyed like the monitors table, all monitors without it
+    pub monitors: Option<Vec<String>>,
+}
+
+impl HookConfig {
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+            .as_deref()
+            .and_then(|t| parse_interval(t).ok())
+            .unwrap_or(DEFAULT_HOOK_TIMEOUT)
+    }
+
+    pub fn applies_to(&self, monitor: &MonitorInfo) -> bool {
+        includes(&self.monitors, monitor)
+    }
+}
+
+// Text drawn onto the rendered image of a monitor
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OverlayConfig {
+    // One of overlay::KINDS
+    pub show: String,
+    // A TrueType or OpenType font file
+    pub font: String,
+    // Height of a line in pixels
+    pub size: Option<f32>,
+    // Like "#ffffff"
+    pub color: Option<String>,
+    pub shadow: Option<bool>,
+    // Like "bottom-right" or "center"
+    pub anchor: Option<String>,
+    // Distance from the edges of the screen in pixels
+    pub margin: Option<f32>,
+    // Only used by date, a strftime format like "%A, %e %B"
+    pub format: Option<String>,
+    // Only used by quote, a text file with quotes separated by empty lines
+    pub file: Option<String>,
+    // Keyed like the monitors table, all monitors without it
+    pub monitors: Option<Vec<String>>,
+}
+
+impl OverlayConfig {
+    pub fn applies_to(&self, monitor: &MonitorInfo) -> bool {
+        includes(&self.monitors, monitor)
+    }
+}
+
+// A display layout, e.g. "office" or "home". It is selected when exactly its monitors are
+// connected, and its monitor settings are applied on top of the top-level ones.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(default)]
+    pub monitors: BTreeMap<String, MonitorConfig>,
+}
+
+impl Profile {
+    pub fn matches(&self, monitors: &[MonitorInfo]) -> bool {
+        if self.monitors.len() != monitors.len() {
+            return false;
+        }
+
+        monitors.iter().all(|monitor| {
+            let entries: Vec<&MonitorConfig> = monitor_keys(monitor)
+                .iter()
+                .filter_map(|key| self.monitors.get(key))
+                .collect();
+
+            !entries.is_empty()
+                && entries.iter().all(|entry| {
+                    match entry.resolution.as_deref().map(parse_resolution) {
+                        Some(Ok((width, height))) => {
+                            width == monitor.width && height == monitor.height
+                        }
+                        _ => true,
+                    }
+                })
+        })
+    }
+}
+
+// Settings for one monitor after applying its overrides on top of the defaults
+#[derive(Debug, Clone)]
+pub struct MonitorSettings {
+    pub sources: Vec<PathBuf>,
+    pub mode: Mode,
+    pub backdrop: Backdrop,
+    // Kept clear of the subject and of overlays
+    pub insets: Insets,
+    pub format: OutputFormat,
+    pub quality: u8,
+    pub aspect_tolerance: f64,
+    pub on_this_day: bool,
+    pub dedupe: bool,
+    // From 0 for untouched to 1 for black
+    pub darken: f64,
+    // What dark mode shows instead, on systems with a separate dark background. Missing when it
+    // looks the same as light mode.
+    pub dark: Option<DarkVariant>,
+    pub palette: bool,
+    // Those configured for this monitor
+    pub hooks: Vec<HookConfig>,
+    pub overlays: Vec<OverlayConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DarkVariant {
+    // Other images, picked on their own
+    Sources(Vec<PathBuf>),
+    // The light image darkened by this much
+    Darken(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    // Lossless
+    WebP,
+    // JPEG for photos and PNG for graphics, decided per image by encode::resolve
+    Auto,
+}
+
+impl OutputFormat {
+    // Auto has to be resolved first, PNG is the safe guess when it wasn't
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Png | OutputFormat::Auto => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::WebP => "webp",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "png" => Ok(OutputFormat::Png),
+            "jpeg" | "jpg" => Ok(OutputFormat::Jpeg),
+            "webp" => Ok(OutputFormat::WebP),
+            "auto" => Ok(OutputFormat::Auto),
+            _ => Err(format!(
+                "unknown format '{}', expected one of: png, jpeg, webp, auto",
+                s
+            )),
+        }
+    }
+}
+
+pub fn config_path() -> Result<PathBuf, String> {
+    match dirs::config_dir() {
+        Some(dir) => Ok(dir.join("background_manager").join("config.toml")),
+        None => Err("Could not determine the user config directory".to_string()),
+    }
+}
+
+impl Config {
+    // Load the user's config file. A missing file is not an error, everything has a default.
+    pub fn load() -> Result<Config, String> {
+        let path = config_path()?;
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        // Systems with a separate dark background switch to it by themselves
+        let mut config = Config::load_from(&path)?;
+        if config.follows_appearance() && !os_level::HAS_DARK_SLOT {
+            config.appearance = os_level::get_appearance().ok();
+        }
+        Ok(config)
+    }
+
+    // Whether dark sources or darkening are configured anywhere
+    fn follows_appearance(&self) -> bool {
+        let mut monitors = self
+            .monitors
+            .values()
+            .chain(self.profiles.values().flat_map(|p| p.monitors.values()));
+        self.dark_sources.is_some()
+            || self.darken.is_some()
+            || monitors.any(|m| m.dark_sources.is_some() || m.darken.is_some())
+    }
+
+    // Files that can't be found are only warned about, they may be on a drive that isn't
+    // mounted yet
+    pub fn load_from(path: &Path) -> Result<Config, String> {
+        let config = Config::parse(path)?;
+        for problem in config.missing_files() {
+            warn_once(format!("Warning: {}: {}", path.display(), problem));
+        }
+        Ok(config)
+    }
+
+    // Like load_from, but files that can't be found are errors too. Used by `config check`.
+    pub fn check(path: &Path) -> Result<Config, String> {
+        let config = Config::parse(path)?;
+        let missing = config.missing_files();
+        if !missing.is_empty() {
+            return Err(format!("{}:\n  {}", path.display(), missing.join("\n  ")));
+        }
+        Ok(config)
+    }
+
+    fn parse(path: &Path) -> Result<Config, String> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => return Err(format!("Failed to read '{}': {}", path.display(), e)),
+        };
+
+        let config: Config = match toml::from_str(&contents) {
+            Ok(config) => config,
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+
+        let errors = config.validate();
+        if !errors.is_empty() {
+            return Err(format!("{}:\n  {}", path.display(), errors.join("\n  ")));
+        }
+
+        Ok(config)
+    }
+
+    // Check every value, returning one message per offending key. Whether the files they name
+    // exist is left to missing_files.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        validate_value::<Mode>("mode", &self.mode, &mut errors);
+        validate_value::<Backdrop>("backdrop", &self.backdrop, &mut errors);
+        validate_value::<Insets>("insets", &self.insets, &mut errors);
+        validate_value::<OutputFormat>("format", &self.format, &mut errors);
+        if self.format() == OutputFormat::WebP && !os_level::SHOWS_WEBP {
+            errors.push(
+                "format: the desktop here can't show WebP backgrounds, use png, jpeg or auto"
+                    .to_string(),
+            );
+        }
+        if let Some(quality) = self.quality {
+            if !(1..=100).contains(&quality) {
+                errors.push(format!(
+                    "quality: must be between 1 and 100, got {}",
+                    quality
+                ));
+            }
+        }
+        validate_fraction("aspect_tolerance", self.aspect_tolerance, &mut errors);
+        validate_fraction("darken", self.darken, &mut errors);
+
+        if let Some(interval) = &self.interval {
+            if let Err(e) = parse_interval(interval) {
+                errors.push(format!("interval: {}", e));
+            }
+        }
+
+        for (key, monitor) in &self.monitors {
+            let prefix = format!("monitors.{}", quote_key(key));
+            if monitor.resolution.is_some() {
+                errors.push(format!(
+                    "{}.resolution: only allowed for monitors inside a profile",
+                    prefix
+                ));
+            }
+            validate_monitor(&prefix, monitor, &mut errors);
+        }
+
+        for (name, profile) in &self.profiles {
+            let prefix = format!("profiles.{}.monitors", quote_key(name));
+            if profile.monitors.is_empty() {
+                errors.push(format!("{}: a profile needs at least one monitor", prefix));
+            }
+            for (key, monitor) in &profile.monitors {
+                validate_monitor(
+                    &format!("{}.{}", prefix, quote_key(key)),
+                    monitor,
+                    &mut errors,
+                );
+            }
+        }
+
+        for (i, remote) in self.remote.iter().enumerate() {
+            if let Err(e) = remote::provider_from_config(remote) {
+                errors.push(format!("remote[{}]: {}", i, e));
+            }
+            if remote.count == Some(0) {
+                errors.push(format!("remote[{}].count: must be at least 1", i));
+            }
+        }
+
+        for (i, hook) in self.hook.iter().enumerate() {
+            if hook.command.trim().is_empty() {
+                errors.push(format!("hook[{}].command: must not be empty", i));
+            }
+            if let Some(timeout) = &hook.timeout {
+                if let Err(e) = parse_interval(timeout) {
+                    errors.push(format!("hook[{}].timeout: {}", i, e));
+                }
+            }
+        }
+
+        for (i, overlay) in self.overlay.iter().enumerate() {
+            validate_overlay(&format!("overlay[{}]", i), overlay, &mut errors);
+        }
+
+        if let Some(location) = &self.location {
+            if !(-90.0..=90.0).contains(&location.latitude) {
+                errors.push(format!(
+                    "location.latitude: must be between -90 and 90, got {}",
+                    location.latitude
+                ));
+            }
+            if !(-180.0..=180.0).contains(&location.longitude) {
+                errors.push(format!(
+                    "location.longitude: must be between -180 and 180, got {}",
+                    location.longitude
+                ));
+            }
+        }
+
+        for (i, rule) in self.schedule.iter().enumerate() {
+            match rule.from.parse::<TimeSpec>() {
+                Ok(spec) if spec.needs_location() && self.location.is_none() => errors.push(
+                    format!("schedule[{}].from: '{}' needs a [location]", i, rule.from),
+                ),
+                Ok(_) => {}
+                Err(e) => errors.push(format!("schedule[{}].from: {}", i, e)),
+            }
+            if rule.sources.is_empty() {
+                errors.push(format!(
+                    "schedule[{}].sources: needs at least one source",
+                    i
+                ));
+            }
+        }
+
+        errors
+    }
+
+    // Sources, dynamic wallpapers, fonts and quote files that can't be found or read right now,
+    // one message per key
+    pub fn missing_files(&self) -> Vec<String> {
+        let mut missing = Vec::new();
+
+        check_sources("sources", &self.sources, &mut missing);
+        if let Some(dark_sources) = &self.dark_sources {
+            check_sources("dark_sources", dark_sources, &mut missing);
+        }
+        check_dynamic("dynamic", &self.dynamic, self.location, &mut missing);
+
+        let monitors = self
+            .monitors
+            .iter()
+            .map(|(key, monitor)| (format!("monitors.{}", quote_key(key)), monitor))
+            .chain(self.profiles.iter().flat_map(|(name, profile)| {
+                profile.monitors.iter().map(move |(key, monitor)| {
+                    let prefix = format!("profiles.{}.monitors", quote_key(name));
+                    (format!("{}.{}", prefix, quote_key(key)), monitor)
+                })
+            }));
+        for (prefix, monitor) in monitors {
+            if let Some(sources) = &monitor.sources {
+                check_sources(&format!("{}.sources", prefix), sources, &mut missing);
+            }
+            if let Some(dark_sources) = &monitor.dark_sources {
+                check_sources(
+                    &format!("{}.dark_sources", prefix),
+                    dark_sources,
+                    &mut missing,
+                );
+            }
+            check_dynamic(
+                &format!("{}.dynamic", prefix),
+                &monitor.dynamic,
+                self.location,
+                &mut missing,
+            );
+        }
+
+        for (i, rule) in self.schedule.iter().enumerate() {
+            check_sources(
+                &format!("schedule[{}].sources", i),
+                &rule.sources,
+                &mut missing,
+            );
+        }
+
+        for (i, overlay) in self.overlay.iter().enumerate() {
+            if let Err(e) = overlay::load_font(&expand_path(&overlay.font)) {
+                missing.push(format!("overlay[{}].font: {}", i, e));
+            }
+            if let ("quote", Some(file)) = (overlay.show.as_str(), &overlay.file) {
+                if !expand_path(file).exists() {
+                    missing.push(format!("overlay[{}].file: '{}' does not exist", i, file));
+                }
+            }
+        }
+
+        missing
+    }
+
+    // The first profile, in alphabetical order, made for exactly these monitors
+    pub fn matching_profile(&self, monitors: &[MonitorInfo]) -> Option<(&str, &Profile)> {
+        self.profiles
+            .iter()
+            .find(|(_, profile)| profile.matches(monitors))
+            .map(|(name, profile)| (name.as_str(), profile))
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+            .as_deref()
+            .and_then(|i| parse_interval(i).ok())
+            .unwrap_or(DEFAULT_INTERVAL)
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        parse_or(&self.format, OutputFormat::Png)
+    }
+
+    pub fn quality(&self) -> u8 {
+        self.quality.unwrap_or(DEFAULT_QUALITY)
+    }
+
+    pub fn crossfade(&self) -> bool {
+        self.crossfade.unwrap_or(true)
+    }
+
+    pub fn settings_for(
+        &self,
+        monitor: &MonitorInfo,
+        profile: Option<&Profile>,
+    ) -> MonitorSettings {
+        let mut sources = &self.sources;
+        let mut mode = &self.mode;
+        let mut backdrop = &self.backdrop;
+        let mut insets = &self.insets;
+        let mut aspect_tolerance = self.aspect_tolerance;
+        let mut on_this_day = self.on_this_day;
+        let mut dynamic = &self.dynamic;
+        let mut dark_sources = &self.dark_sources;
+        let mut darken = self.darken;
+
+        // Apply overrides from the least to the most specific key, the profile goes last
+        let keys = monitor_keys(monitor);
+        let mut layers: Vec<&MonitorConfig> = keys
+            .iter()
+            .filter_map(|key| self.monitors.get(key))
+            .collect();
+        if let Some(profile) = profile {
+            layers.extend(keys.iter().filter_map(|key| profile.monitors.get(key)));
+        }
+
+        for overrides in layers {
+            if let Some(s) = &overrides.sources {
+                sources = s;
+            }
+            if overrides.mode.is_some() {
+                mode = &overrides.mode;
+            }
+            if overrides.backdrop.is_some() {
+                backdrop = &overrides.backdrop;
+            }
+            if overrides.insets.is_some() {
+                insets = &overrides.insets;
+            }
+            if overrides.aspect_tolerance.is_some() {
+                aspect_tolerance = overrides.aspect_tolerance;
+            }
+            if overrides.on_this_day.is_some() {
+                on_this_day = overrides.on_this_day;
+            }
+            if overrides.dark_sources.is_some() {
+                dark_sources = &overrides.dark_sources;
+            }
+            if overrides.darken.is_some() {
+                darken = overrides.darken;
+            }
+            // Sources given next to a dynamic wallpaper replace it
+            if overrides.dynamic.is_some() {
+                dynamic = &overrides.dynamic;
+            } else if overrides.sources.is_some() {
+                dynamic = &None;
+            }
+        }
+
+        let now = chrono::Local::now().naive_local();
+        let mut sources: Vec<PathBuf> = sources.iter().map(|s| expand_path(s)).collect();
+
+        let mut darken_by = 0.0;
+        let mut dark = None;
+        if os_level::HAS_DARK_SLOT {
+            dark = match dark_sources {
+                Some(dark) => Some(DarkVariant::Sources(
+                    dark.iter().map(|s| expand_path(s)).collect(),
+                )),
+                None => darken
+                    .filter(|amount| *amount > 0.0)
+                    .map(DarkVariant::Darken),
+            };
+        } else if self.appearance == Some(Appearance::Dark) {
+            match dark_sources {
+                Some(dark) => sources = dark.iter().map(|s| expand_path(s)).collect(),
+                None => darken_by = darken.unwrap_or(0.0),
+            }
+        }
+        if let Some(name) = dynamic {
+            match DynamicWallpaper::load(name) {
+                Ok(wallpaper) => {
+                    if let Some(frame) = wallpaper.active_frame(now, self.location) {
+                        sources = vec![frame.image.clone()];
+                        dark = dark.filter(|dark| matches!(dark, DarkVariant::Darken(_)));
+                    }
+                }
+                Err(e) => eprintln!("Warning: {}", e),
+            }
+        }
+
+        // The schedule wins over everything else while one of its rules is active, dark sources
+        // included
+        if let Some(i) = schedule::active_rule(&self.schedule, self.location, monitor, now) {
+            sources = self.schedule[i]
+                .sources
+                .iter()
+                .map(|s| expand_path(s))
+                .collect();
+            dark = dark.filter(|dark| matches!(dark, DarkVariant::Darken(_)));
+        }
+
+        MonitorSettings {
+            sources,
+            mode: parse_or(mode, Mode::Fit),
+            backdrop: parse_or(backdrop, Backdrop::Blur),
+            insets: parse_or(insets, monitor.insets),
+            format: self.format(),
+            quality: self.quality(),
+            aspect_tolerance: aspect_tolerance.unwrap_or(DEFAULT_ASPECT_TOLERANCE),
+            on_this_day: on_this_day.unwrap_or(false),
+            dedupe: self.dedupe.unwrap_or(false),
+            darken: darken_by,
+            dark,
+            palette: self.palette.unwrap_or(false),
+            hooks: self
+                .hook
+                .iter()
+                .filter(|hook| hook.applies_to(monitor))
+                .cloned()
+                .collect(),
+            overlays: self
+                .overlay
+                .iter()
+                .filter(|overlay| overlay.applies_to(monitor))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+impl MonitorSettings {
+    // The same settings for rendering at a fraction of the monitor's size, as previews do
+    pub fn scaled(&self, factor: f32) -> MonitorSettings {
+        MonitorSettings {
+            insets: self.insets.scaled(factor),
+            overlays: self
+                .overlays
+                .iter()
+                .map(|entry| overlay::scaled(entry, factor))
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+impl Default for MonitorSettings {
+    fn default() -> Self {
+        MonitorSettings {
+            sources: Vec::new(),
+            mode: Mode::Fit,
+            backdrop: Backdrop::Blur,
+            insets: Insets::default(),
+            format: OutputFormat::Png,
+            quality: DEFAULT_QUALITY,
+            aspect_tolerance: DEFAULT_ASPECT_TOLERANCE,
+            on_this_day: false,
+            dedupe: false,
+            darken: 0.0,
+            dark: None,
+            palette: false,
+            hooks: Vec::new(),
+            overlays: Vec::new(),
+        }
+    }
+}
+
+// Whether a list of monitor keys names the monitor, a missing list means all monitors
+fn includes(keys: &Option<Vec<String>>, monitor: &MonitorInfo) -> bool {
+    match keys {
+        Some(keys) => {
+            let own = monitor_keys(monitor);
+            keys.iter().any(|key| own.contains(key))
+        }
+        None => true,
+    }
+}
+
+// Every key a monitor can be referred to by, from the least to the most specific
+pub fn monitor_keys(monitor: &MonitorInfo) -> [String; 3] {
+    [
+        monitor.id.to_string(),
+        monitor.name.clone(),
+        monitor.identity.clone(),
+    ]
+}
+
+fn parse_or<T: FromStr>(value: &Option<String>, default: T) -> T {
+    value
+        .as_deref()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+fn validate_value<T: FromStr<Err = String>>(
+    key: &str,
+    value: &Option<String>,
+    errors: &mut Vec<String>,
+) {
+    if let Some(value) = value {
+        if let Err(e) = value.parse::<T>() {
+            errors.push(format!("{}: {}", key, e));
+        }
+    }
+}
+
+fn validate_monitor(prefix: &str, monitor: &MonitorConfig, errors: &mut Vec<String>) {
+    if let Some(resolution) = &monitor.resolution {
+        if let Err(e) = parse_resolution(resolution) {
+            errors.push(format!("{}.resolution: {}", prefix, e));
+        }
+    }
+    validate_value::<Mode>(&format!("{}.mode", prefix), &monitor.mode, errors);
+    validate_value::<Backdrop>(&format!("{}.backdrop", prefix), &monitor.backdrop, errors);
+    validate_value::<Insets>(&format!("{}.insets", prefix), &monitor.insets, errors);
+    validate_fraction(
+        &format!("{}.aspect_tolerance", prefix),
+        monitor.aspect_tolerance,
+        errors,
+    );
+    validate_fraction(&format!("{}.darken", prefix), monitor.darken, errors);
+}
+
+// The wallpaper has to be imported, and those following the sun need to know where it is
+fn check_dynamic(
+    key: &str,
+    name: &Option<String>,
+    location: Option<Location>,
+    missing: &mut Vec<String>,
+) {
+    if let Some(name) = name {
+        match DynamicWallpaper::load(name) {
+            Ok(wallpaper) if wallpaper.follows_sun() && location.is_none() => missing.push(
+                format!("{}: '{}' follows the sun and needs a [location]", key, name),
+            ),
+            Ok(_) => {}
+            Err(e) => missing.push(format!("{}: {}", key, e)),
+        }
+    }
+}
+
+fn validate_overlay(prefix: &str, overlay: &OverlayConfig, errors: &mut Vec<String>) {
+    if !overlay::KINDS.contains(&overlay.show.as_str()) {
+        errors.push(format!(
+            "{}.show: unknown overlay '{}', expected one of: {}",
+            prefix,
+            overlay.show,
+            overlay::KINDS.join(", ")
+        ));
+    }
+    if let Some(size) = overlay.size {
+        if size <= 0.0 {
+            errors.push(format!("{}.size: must be above 0, got {}", prefix, size));
+        }
+    }
+    if let Some(margin) = overlay.margin {
+        if margin < 0.0 {
+            errors.push(format!(
+                "{}.margin: must not be negative, got {}",
+                prefix, margin
+            ));
+        }
+    }
+    if let Some(color) = &overlay.color {
+        if image_proc::parse_color(color).is_none() {
+            errors.push(format!(
+                "{}.color: unknown color '{}', expected something like '#ffffff'",
+                prefix, color
+            ));
+        }
+    }
+    validate_value::<Anchor>(&format!("{}.anchor", prefix), &overlay.anchor, errors);
+
+    if let Some(format) = &overlay.format {
+        if !overlay::valid_date_format(format) {
+            errors.push(format!(
+                "{}.format: invalid date format '{}'",
+                prefix, format
+            ));
+        }
+    }
+
+    if overlay.show == "quote" && overlay.file.is_none() {
+        errors.push(format!("{}.file: needed to show quotes", prefix));
+    }
+}
+
+fn validate_fraction(key: &str, value: Option<f64>, errors: &mut Vec<String>) {
+    if let Some(value) = value {
+        if !(0.0..=1.0).contains(&value) {
+            errors.push(format!("{}: must be between 0 and 1, got {}", key, value));
+        }
+    }
+}
+
+fn check_sources(key: &str, sources: &[String], missing: &mut Vec<String>) {
+    for (i, source) in sources.iter().enumerate() {
+        if !expand_path(source).exists() {
+            missing.push(format!("{}[{}]: '{}' does not exist", key, i, source));
+        }
+    }
+}
+
+// Loading happens on every tick of the daemon, the same warning shouldn't fill the log
+fn warn_once(warning: String) {
+    static WARNED: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+    if WARNED.lock().unwrap().insert(warning.clone()) {
+        eprintln!("{}", warning);
+    }
+}
+
+// Keys with spaces or punctuation have to be quoted to be valid TOML
+fn quote_key(key: &str) -> String {
+    if key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        key.to_string()
+    } else {
+        format!("{:?}", key)
+    }
+}
+
+pub fn expand_path(path: &str) -> PathBuf {
+    if let Some(rest) = path.strip_prefix("~/") {
+        if let Some(home) = dirs::home_dir() {
+            return home.join(rest);
+        }
+    }
+    PathBuf::from(path)
+}
+
+// Accepts a number followed by a unit, e.g. "90s", "30m", "2h" or "1d"
+pub fn parse_interval(interval: &str) -> Result<Duration, String> {
+    let interval = interval.trim();
+    let split = interval
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(interval.len());
+    let (number, unit) = interval.split_at(split);
+
+    let number: u64 = match number.parse() {
+        Ok(n) if n > 0 => n,
+        _ => {
+            return Err(format!(
+                "invalid interval '{}', expected something like '30m'",
+                interval
+            ))
+        }
+    };
+
+    let seconds = match unit.trim() {
+        "s" => number,
+        "m" => number * 60,
+        "h" => number * 60 * 60,
+        "d" => number * 60 * 60 * 24,
+        _ => {
+            return Err(format!(
+                "invalid interval unit in '{}', expected one of: s, m, h, d",
+                interval
+            ))
+        }
+    };
+
+    Ok(Duration::from_secs(seconds))
+}
+
+// Accepts "2560x1440"
+pub fn parse_resolution(resolution: &str) -> Result<(usize, usize), String> {
+    let parsed = resolution
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)));
+
+    match parsed {
+        Some((width, height)) => Ok((width, height)),
+        None => Err(format!(
+            "invalid resolution '{}', expected something like '2560x1440'",
+            resolution
+        )),
+    }
+}
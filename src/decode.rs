@@ -0,0 +1,345 @@
+use image::metadata::Orientation;
+use image::ImageFormat;
+#[cfg(feature = "heif")]
+use libheif_rs::{ColorSpace, HeifContext, ImageHandle, LibHeif, RgbChroma};
+use photon_rs::PhotonImage;
+use resvg::{tiny_skia, usvg};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Cursor, Read, Seek};
+use std::path::Path;
+
+// Enough of the start of a file to recognize any of the formats, SVGs may start with a long
+// XML prolog
+const SNIFF_LENGTH: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Jpeg,
+    Png,
+    Gif,
+    Bmp,
+    Tiff,
+    WebP,
+    Avif,
+    Heic,
+    Svg,
+}
+
+// Recognize the format by the magic bytes at the start of the file
+pub fn sniff(header: &[u8]) -> Option<Format> {
+    if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some(Format::Jpeg)
+    } else if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(Format::Png)
+    } else if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+        Some(Format::Gif)
+    } else if header.starts_with(b"BM") {
+        Some(Format::Bmp)
+    } else if header.starts_with(b"II*\0") || header.starts_with(b"MM\0*") {
+        Some(Format::Tiff)
+    } else if header.starts_with(b"RIFF") && header.get(8..12) == Some(&b"WEBP"[..]) {
+        Some(Format::WebP)
+    } else if header.get(4..8) == Some(&b"ftyp"[..]) {
+        // The major brand of the ISO media file tells AVIF from HEIC
+        match header.get(8..12) {
+            Some(b"avif" | b"avis") => Some(Format::Avif),
+            Some(b"heic" | b"heix" | b"hevc" | b"hevx" | b"heim" | b"heis" | b"mif1" | b"msf1") => {
+                Some(Format::Heic)
+            }
+            _ => None,
+        }
+    } else if looks_like_svg(header) {
+        Some(Format::Svg)
+    } else {
+        None
+    }
+}
+
+fn looks_like_svg(header: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(header);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    text.starts_with('<') && text.contains("<svg")
+}
+
+pub fn sniff_file(path: &Path) -> Option<Format> {
+    let mut header = Vec::with_capacity(SNIFF_LENGTH);
+    File::open(path)
+        .ok()?
+        .take(SNIFF_LENGTH as u64)
+        .read_to_end(&mut header)
+        .ok()?;
+    sniff(&header)
+}
+
+// Decode an image of any supported format, whatever its extension says. SVGs are drawn to
+// cover the target size when there is one, so they stay sharp on any monitor.
+pub fn open(path: &Path, target: Option<(u32, u32)>) -> Result<PhotonImage, String> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) => return Err(format!("Failed to read '{}': {}", path.display(), e)),
+    };
+
+    let image = match sniff(&data) {
+        Some(Format::Svg) => rasterize_svg(&data, target),
+        Some(Format::Avif | Format::Heic) => decode_heif(&data),
+        Some(format) => decode_raster(&data, format),
+        None => Err(
+            "not in a supported format (JPEG, PNG, WebP, AVIF, HEIC, TIFF, BMP, GIF or SVG)"
+                .to_string(),
+        ),
+    };
+    image.map_err(|e| format!("Failed to open '{}': {}", path.display(), e))
+}
+
+// Width and height from the header of the file, or the size an SVG declares. Photos the
+// camera marked as turned on their side report the size they are shown at.
+pub fn dimensions(path: &Path) -> Option<(usize, usize)> {
+    let (width, height) = match imagesize::size(path) {
+        Ok(size) if has_exif(sniff_file(path)) && turned_sideways(path) => {
+            (size.height, size.width)
+        }
+        Ok(size) => (size.width, size.height),
+        Err(_) if sniff_file(path) == Some(Format::Svg) => {
+            let tree = parse_svg(&fs::read(path).ok()?).ok()?;
+            let size = tree.size();
+            (
+                size.width().round() as usize,
+                size.height().round() as usize,
+            )
+        }
+        Err(_) => return None,
+    };
+
+    if width == 0 || height == 0 {
+        None
+    } else {
+        Some((width, height))
+    }
+}
+
+fn decode_raster(data: &[u8], format: Format) -> Result<PhotonImage, String> {
+    let format = match format {
+        Format::Jpeg => ImageFormat::Jpeg,
+        Format::Png => ImageFormat::Png,
+        // Only the first frame of an animation is decoded
+        Format::Gif => ImageFormat::Gif,
+        Format::Bmp => ImageFormat::Bmp,
+        Format::Tiff => ImageFormat::Tiff,
+        Format::WebP => ImageFormat::WebP,
+        Format::Avif | Format::Heic | Format::Svg => {
+            return Err(format!("{:?} is not a raster format", format))
+        }
+    };
+
+    let mut image = image::load_from_memory_with_format(data, format).map_err(|e| e.to_string())?;
+    if let Some(orientation) = orientation(&mut Cursor::new(data)) {
+        image.apply_orientation(orientation);
+    }
+    let image = image.to_rgba8();
+    let (width, height) = image.dimensions();
+    Ok(PhotonImage::new(image.into_raw(), width, height))
+}
+
+// libheif already turns HEIC and AVIF images the way their container says, the others are
+// stored as the camera's sensor saw them
+fn has_exif(format: Option<Format>) -> bool {
+    matches!(
+        format,
+        Some(Format::Jpeg | Format::Png | Format::Tiff | Format::WebP)
+    )
+}
+
+// How the EXIF Orientation tag says to turn and flip the stored pixels to show them upright
+fn orientation(reader: &mut (impl BufRead + Seek)) -> Option<Orientation> {
+    let exif = exif::Reader::new().read_from_container(reader).ok()?;
+    let field = exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?;
+    Orientation::from_exif(field.value.get_uint(0)? as u8)
+}
+
+// Orientations 5 to 8 turn the image by a quarter, so width and height trade places
+fn turned_sideways(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    matches!(
+        orientation(&mut BufReader::new(file)),
+        Some(
+            Orientation::Rotate90
+                | Orientation::Rotate270
+                | Orientation::Rotate90FlipH
+                | Orientation::Rotate270FlipH
+        )
+    )
+}
+
+#[cfg(feature = "heif")]
+fn decode_heif(data: &[u8]) -> Result<PhotonImage, String> {
+    let context = HeifContext::read_from_bytes(data).map_err(|e| e.to_string())?;
+    let primary = context.primary_image_handle().map_err(|e| e.to_string())?;
+    heif_image(&LibHeif::new(), &primary)
+}
+
+#[cfg(not(feature = "heif"))]
+fn decode_heif(_data: &[u8]) -> Result<PhotonImage, String> {
+    Err("this build has no AVIF or HEIC support (build with --features heif)".to_string())
+}
+
+#[cfg(feature = "heif")]
+pub fn heif_image(lib_heif: &LibHeif, handle: &ImageHandle) -> Result<PhotonImage, String> {
+    let image = lib_heif
+        .decode(handle, ColorSpace::Rgb(RgbChroma::Rgba), None)
+        .map_err(|e| e.to_string())?;
+    let Some(plane) = image.planes().interleaved else {
+        return Err("the image has no pixels".to_string());
+    };
+
+    // Rows may be padded, photon wants them packed
+    let row = plane.width as usize * 4;
+    let pixels: Vec<u8> = plane
+        .data
+        .chunks(plane.stride)
+        .take(plane.height as usize)
+        .flat_map(|line| &line[..row])
+        .copied()
+        .collect();
+    Ok(PhotonImage::new(pixels, plane.width, plane.height))
+}
+
+fn parse_svg(data: &[u8]) -> Result<usvg::Tree, String> {
+    usvg::Tree::from_data(data, &usvg::Options::default()).map_err(|e| e.to_string())
+}
+
+// Scale the drawing to cover the target, keeping its aspect ratio, or else use its own size
+fn rasterize_svg(data: &[u8], target: Option<(u32, u32)>) -> Result<PhotonImage, String> {
+    let tree = parse_svg(data)?;
+    let size = tree.size();
+    let scale = match target {
+        Some((width, height)) => (width as f32 / size.width()).max(height as f32 / size.height()),
+        None => 1.0,
+    };
+
+    let width = (size.width() * scale).round().max(1.0) as u32;
+    let height = (size.height() * scale).round().max(1.0) as u32;
+    let Some(mut pixmap) = tiny_skia::Pixmap::new(width, height) else {
+        return Err(format!("can't draw it at {}x{}", width, height));
+    };
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    // tiny-skia keeps colors premultiplied by alpha, photon doesn't
+    let pixels: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    Ok(PhotonImage::new(pixels, width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // Every fixture is 16x8, red on the left half and blue on the right
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("test_resources")
+            .join(name)
+    }
+
+    fn pixel(image: &PhotonImage, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * image.get_width() + x) * 4) as usize;
+        let raw = image.get_raw_pixels();
+        [raw[i], raw[i + 1], raw[i + 2], raw[i + 3]]
+    }
+
+    fn assert_red_and_blue(image: &PhotonImage) {
+        assert_eq!((image.get_width(), image.get_height()), (16, 8));
+        let [r, g, b, _] = pixel(image, 2, 4);
+        assert!(r > 200 && g < 50 && b < 50, "left is {:?}", (r, g, b));
+        let [r, g, b, _] = pixel(image, 13, 4);
+        assert!(r < 50 && g < 50 && b > 200, "right is {:?}", (r, g, b));
+    }
+
+    #[test]
+    fn sniffs_every_fixture() {
+        for (name, format) in [
+            ("test_a.jpg", Format::Jpeg),
+            ("test_b.bmp", Format::Bmp),
+            ("test_c.tiff", Format::Tiff),
+            ("test_d.webp", Format::WebP),
+            ("test_e.avif", Format::Avif),
+            ("test_f.gif", Format::Gif),
+            ("test_g.svg", Format::Svg),
+        ] {
+            assert_eq!(sniff_file(&fixture(name)), Some(format), "{}", name);
+        }
+    }
+
+    #[test]
+    fn sniff_rejects_unknown_data() {
+        assert_eq!(sniff(b"just some text"), None);
+        assert_eq!(sniff(b""), None);
+    }
+
+    #[test]
+    fn opens_raster_fixtures() {
+        for name in ["test_b.bmp", "test_c.tiff", "test_d.webp"] {
+            let image = open(&fixture(name), None).unwrap();
+            assert_red_and_blue(&image);
+        }
+    }
+
+    #[cfg(feature = "heif")]
+    #[test]
+    fn opens_avif() {
+        assert_red_and_blue(&open(&fixture("test_e.avif"), None).unwrap());
+    }
+
+    #[cfg(not(feature = "heif"))]
+    #[test]
+    fn avif_needs_the_heif_feature() {
+        let error = open(&fixture("test_e.avif"), None).unwrap_err();
+        assert!(error.contains("--features heif"), "{}", error);
+    }
+
+    #[test]
+    fn gif_gives_the_first_frame() {
+        // The first frame is all red, the second all blue
+        let image = open(&fixture("test_f.gif"), None).unwrap();
+        assert_eq!((image.get_width(), image.get_height()), (16, 8));
+        let [r, g, b, _] = pixel(&image, 13, 4);
+        assert!(r > 200 && g < 50 && b < 50, "got {:?}", (r, g, b));
+    }
+
+    #[test]
+    fn svg_is_drawn_at_the_target_size() {
+        assert_red_and_blue(&open(&fixture("test_g.svg"), None).unwrap());
+
+        let image = open(&fixture("test_g.svg"), Some((64, 32))).unwrap();
+        assert_eq!((image.get_width(), image.get_height()), (64, 32));
+
+        // Other aspect ratios are covered, not fitted
+        let image = open(&fixture("test_g.svg"), Some((100, 100))).unwrap();
+        assert_eq!((image.get_width(), image.get_height()), (200, 100));
+    }
+
+    #[test]
+    fn dimensions_of_fixtures() {
+        for name in [
+            "test_b.bmp",
+            "test_c.tiff",
+            "test_d.webp",
+            "test_f.gif",
+            "test_g.svg",
+        ] {
+            assert_eq!(dimensions(&fixture(name)), Some((16, 8)), "{}", name);
+        }
+    }
+}
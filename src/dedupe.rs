@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::decode;
+use crate::state;
+
+// Hashes differing in at most this many of their 64 bits are the same picture
+pub const DUPLICATE_DISTANCE: u32 = 10;
+
+// Perceptual hashes of images, so each file is only hashed again after it changed. Stored as
+// JSON in the user data directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HashIndex {
+    #[serde(default)]
+    pub images: BTreeMap<PathBuf, HashedImage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HashedImage {
+    // Modification time of the file when it was hashed, in seconds since the UNIX epoch.
+    pub modified: u64,
+    // The difference hash, missing when the image could not be opened.
+    pub hash: Option<u64>,
+}
+
+fn index_path() -> Result<PathBuf, String> {
+    Ok(state::data_dir()?.join("hashes.json"))
+}
+
+impl HashIndex {
+    pub fn load() -> Result<HashIndex, String> {
+        let path = index_path()?;
+        if !path.exists() {
+            return Ok(HashIndex::default());
+        }
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => return Err(format!("Failed to read '{}': {}", path.display(), e)),
+        };
+
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse '{}': {}", path.display(), e))
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = index_path()?;
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize the hash index: {}", e))?;
+
+        fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write '{}': {}", path.display(), e))
+    }
+
+    // Hashes every image that is new or changed since it was hashed. Returns how many were.
+    pub fn update(&mut self, images: &[PathBuf]) -> usize {
+        let mut hashed = 0;
+        for path in images {
+            let modified = modified_secs(path);
+            if let Some(known) = self.images.get(path) {
+                if known.modified == modified {
+                    continue;
+                }
+            }
+
+            let hash = match dhash(path) {
+                Ok(hash) => Some(hash),
+                Err(e) => {
+                    eprintln!("Warning: {}", e);
+                    None
+                }
+            };
+            self.images
+                .insert(path.clone(), HashedImage { modified, hash });
+            hashed += 1;
+        }
+        hashed
+    }
+
+    pub fn hash(&self, path: &Path) -> Option<u64> {
+        self.images.get(path).and_then(|image| image.hash)
+    }
+}
+
+fn modified_secs(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+// Difference hash: shrink to 9x8 gray pixels and record for each pair of neighbours in a row
+// whether brightness goes up. Survives resizing, re-encoding and small color changes.
+pub fn dhash(path: &Path) -> Result<u64, String> {
+    let mut img = decode::open(path, None)?;
+
+    photon_rs::monochrome::grayscale(&mut img);
+    let small =
+        photon_rs::transform::resize(&img, 9, 8, photon_rs::transform::SamplingFilter::Triangle);
+
+    // Gray pixels have the same value in every channel, only red is looked at
+    let pixels = small.get_raw_pixels();
+    let brightness = |x: usize, y: usize| pixels[(y * 9 + x) * 4];
+
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if brightness(x + 1, y) > brightness(x, y) {
+                hash |= 1;
+            }
+        }
+    }
+    Ok(hash)
+}
+
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+// Images showing the same picture, in the order they were given. Each group holds at least two
+// images, images without a hash are never grouped.
+pub fn find_duplicates(images: &[PathBuf], index: &HashIndex) -> Vec<Vec<PathBuf>> {
+    let mut groups: Vec<(u64, Vec<PathBuf>)> = Vec::new();
+
+    for path in images {
+        let Some(hash) = index.hash(path) else {
+            continue;
+        };
+
+        match groups
+            .iter_mut()
+            .find(|(first, _)| distance(*first, hash) <= DUPLICATE_DISTANCE)
+        {
+            Some((_, members)) => members.push(path.clone()),
+            None => groups.push((hash, vec![path.clone()])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(_, members)| members)
+        .filter(|members| members.len() > 1)
+        .collect()
+}
+
+// The copy worth keeping: the most pixels, then the largest file
+pub fn best_copy(group: &[PathBuf]) -> Option<&PathBuf> {
+    group.iter().max_by_key(|path| {
+        let pixels = decode::dimensions(path)
+            .map(|(width, height)| width * height)
+            .unwrap_or(0);
+        let bytes = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        (pixels, bytes)
+    })
+}
+
+// Keep only the best copy of every picture. The index is updated and saved on the way.
+pub fn collapse_duplicates(images: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut index = match HashIndex::load() {
+        Ok(index) => index,
+        Err(e) => {
+            eprintln!("Warning: {}", e);
+            HashIndex::default()
+        }
+    };
+
+    if index.update(&images) > 0 {
+        if let Err(e) = index.save() {
+            eprintln!("Warning: {}", e);
+        }
+    }
+
+    let mut dropped: Vec<PathBuf> = Vec::new();
+    for group in find_duplicates(&images, &index) {
+        let best = best_copy(&group).cloned();
+        dropped.extend(group.into_iter().filter(|path| Some(path) != best.as_ref()));
+    }
+
+    images
+        .into_iter()
+        .filter(|path| !dropped.contains(path))
+        .collect()
+}
@@ -0,0 +1,384 @@
+use chrono::{
+    Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike, Utc,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Location;
+use crate::schedule;
+use crate::state;
+
+const DAY: u64 = 24 * 60 * 60;
+
+// Reading macOS dynamic desktops needs the system libheif
+#[cfg(feature = "heif")]
+mod heic;
+
+// An image sequence following the time of day, imported from a GNOME slideshow or a macOS
+// dynamic desktop. Stored as JSON in its own folder in the user data directory, next to the
+// images extracted from it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DynamicWallpaper {
+    // The file it was imported from.
+    pub source: PathBuf,
+    // When the first cycle started, in local time.
+    pub anchor: NaiveDateTime,
+    // Length of one cycle in seconds, a day for most.
+    pub cycle: u64,
+    // In the order they are shown.
+    pub frames: Vec<DynamicFrame>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DynamicFrame {
+    pub image: PathBuf,
+    // Seconds into the cycle the frame starts at.
+    #[serde(default)]
+    pub offset: u64,
+    // Where the sun is when the frame fits best. Frames with a position are shown while the
+    // sun is closer to it than to that of any other frame, instead of from their offset on.
+    #[serde(default)]
+    pub sun: Option<SunPosition>,
+}
+
+// In degrees, the azimuth clockwise from north.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SunPosition {
+    pub altitude: f64,
+    pub azimuth: f64,
+}
+
+pub fn dynamic_dir() -> Result<PathBuf, String> {
+    Ok(state::data_dir()?.join("dynamic"))
+}
+
+fn wallpaper_path(name: &str) -> Result<PathBuf, String> {
+    Ok(dynamic_dir()?.join(name).join("wallpaper.json"))
+}
+
+impl DynamicWallpaper {
+    pub fn load(name: &str) -> Result<DynamicWallpaper, String> {
+        let path = wallpaper_path(name)?;
+        if !path.exists() {
+            return Err(format!(
+                "No dynamic wallpaper named '{}' was imported",
+                name
+            ));
+        }
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => return Err(format!("Failed to read '{}': {}", path.display(), e)),
+        };
+
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse '{}': {}", path.display(), e))
+    }
+
+    pub fn save(&self, name: &str) -> Result<(), String> {
+        let path = wallpaper_path(name)?;
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize the dynamic wallpaper: {}", e))?;
+
+        fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write '{}': {}", path.display(), e))
+    }
+
+    // The names of all imported dynamic wallpapers, sorted.
+    pub fn list() -> Result<Vec<String>, String> {
+        let dir = dynamic_dir()?;
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => return Err(format!("Failed to read '{}': {}", dir.display(), e)),
+        };
+
+        let mut names: Vec<String> = entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().join("wallpaper.json").exists())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    pub fn follows_sun(&self) -> bool {
+        self.frames.iter().any(|frame| frame.sun.is_some())
+    }
+
+    // The frame shown at a local time. Wallpapers following the sun need a location for that.
+    pub fn active_frame(
+        &self,
+        now: NaiveDateTime,
+        location: Option<Location>,
+    ) -> Option<&DynamicFrame> {
+        if self.follows_sun() {
+            let now = Local.from_local_datetime(&now).earliest()?;
+            let (altitude, azimuth) = schedule::sun_position(now.with_timezone(&Utc), location?);
+            return self
+                .frames
+                .iter()
+                .filter_map(|frame| frame.sun.map(|sun| (frame, sun)))
+                .min_by(|(_, a), (_, b)| {
+                    sun_distance(*a, altitude, azimuth)
+                        .total_cmp(&sun_distance(*b, altitude, azimuth))
+                })
+                .map(|(frame, _)| frame);
+        }
+
+        if self.cycle == 0 {
+            return self.frames.first();
+        }
+        let elapsed = (now - self.anchor)
+            .num_seconds()
+            .rem_euclid(self.cycle as i64) as u64;
+        self.frames
+            .iter()
+            .rev()
+            .find(|frame| frame.offset <= elapsed)
+            .or(self.frames.last())
+    }
+
+    // When each frame starts on a day, to the minute. A frame shows until the next one starts.
+    pub fn timeline(
+        &self,
+        date: NaiveDate,
+        location: Option<Location>,
+    ) -> Vec<(NaiveTime, &DynamicFrame)> {
+        let mut timeline: Vec<(NaiveTime, &DynamicFrame)> = Vec::new();
+        let midnight = date.and_time(NaiveTime::MIN);
+
+        for minute in 0..(DAY / 60) as i64 {
+            let now = midnight + Duration::minutes(minute);
+            let Some(frame) = self.active_frame(now, location) else {
+                continue;
+            };
+            match timeline.last() {
+                Some((_, last)) if last.image == frame.image => {}
+                _ => timeline.push((now.time(), frame)),
+            }
+        }
+        timeline
+    }
+}
+
+// How far apart two positions of the sun are, in degrees. The azimuth wraps around.
+fn sun_distance(sun: SunPosition, altitude: f64, azimuth: f64) -> f64 {
+    let azimuth_difference = (sun.azimuth - azimuth).rem_euclid(360.0);
+    let azimuth_difference = azimuth_difference.min(360.0 - azimuth_difference);
+    (sun.altitude - altitude).hypot(azimuth_difference)
+}
+
+// Parse a GNOME background slideshow. Images are referenced where they are, relative paths
+// are resolved against the folder of the file.
+pub fn parse_gnome_xml(contents: &str, base_dir: &Path) -> Result<DynamicWallpaper, String> {
+    let doc = roxmltree::Document::parse(contents).map_err(|e| e.to_string())?;
+    let root = doc.root_element();
+    if !root.has_tag_name("background") {
+        return Err(format!(
+            "expected a <background> slideshow, found <{}>",
+            root.tag_name().name()
+        ));
+    }
+
+    let anchor = match child(root, "starttime") {
+        Some(start) => parse_start_time(start)?,
+        None => NaiveDateTime::default(),
+    };
+
+    let mut frames: Vec<DynamicFrame> = Vec::new();
+    let mut offset = 0.0;
+    for node in root.children().filter(|n| n.is_element()) {
+        // A transition blends into the next image, which counts as shown from its start
+        let image = match node.tag_name().name() {
+            "static" => child(node, "file"),
+            "transition" => child(node, "to"),
+            _ => continue,
+        };
+        let Some(image) = image.and_then(largest_file) else {
+            return Err(format!(
+                "<{}> is missing the image file",
+                node.tag_name().name()
+            ));
+        };
+        let duration: f64 = child_text(node, "duration")
+            .and_then(|d| d.parse().ok())
+            .ok_or_else(|| format!("<{}> is missing its duration", node.tag_name().name()))?;
+
+        let image = base_dir.join(image);
+        if frames.last().map(|last| &last.image) != Some(&image) {
+            frames.push(DynamicFrame {
+                image,
+                offset: offset as u64,
+                sun: None,
+            });
+        }
+        offset += duration;
+    }
+
+    if frames.is_empty() {
+        return Err("the slideshow has no images".to_string());
+    }
+
+    Ok(DynamicWallpaper {
+        source: PathBuf::new(),
+        anchor,
+        cycle: offset as u64,
+        frames,
+    })
+}
+
+fn child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn child_text<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).and_then(|n| n.text()).map(|t| t.trim())
+}
+
+// <file> holds a path, or one <size width="" height=""> per resolution of the same image
+fn largest_file<'a>(file: roxmltree::Node<'a, '_>) -> Option<&'a str> {
+    let sizes = file.children().filter(|n| n.has_tag_name("size"));
+    let largest = sizes.max_by_key(|size| {
+        let dimension = |name: &str| size.attribute(name).and_then(|v| v.parse::<u64>().ok());
+        dimension("width").unwrap_or(0) * dimension("height").unwrap_or(0)
+    });
+
+    let text = match largest {
+        Some(size) => size.text(),
+        None => file.text(),
+    };
+    text.map(|t| t.trim()).filter(|t| !t.is_empty())
+}
+
+fn parse_start_time(start: roxmltree::Node) -> Result<NaiveDateTime, String> {
+    let field = |name: &str| -> Result<u32, String> {
+        match child_text(start, name) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("invalid <{}> '{}' in <starttime>", name, value)),
+            None => Ok(0),
+        }
+    };
+
+    let (hour, minute, second) = (field("hour")?, field("minute")?, field("second")?);
+    NaiveDate::from_ymd_opt(field("year")? as i32, field("month")?, field("day")?)
+        .and_then(|date| date.and_hms_opt(hour, minute, second))
+        .ok_or_else(|| "invalid <starttime>".to_string())
+}
+
+// Write a GNOME background slideshow that shows each image for the duration and then blends
+// into the next one, looping back to the first. Durations are in seconds.
+pub fn gnome_xml(
+    images: &[PathBuf],
+    duration: u64,
+    transition: u64,
+    start: NaiveDateTime,
+) -> String {
+    let mut xml = String::from("<background>\n  <starttime>\n");
+    let start_fields = [
+        ("year", start.year() as u32),
+        ("month", start.month()),
+        ("day", start.day()),
+        ("hour", start.hour()),
+        ("minute", start.minute()),
+        ("second", start.second()),
+    ];
+    for (tag, value) in start_fields {
+        xml.push_str(&format!("    <{0}>{1}</{0}>\n", tag, value));
+    }
+    xml.push_str("  </starttime>\n");
+
+    for (i, image) in images.iter().enumerate() {
+        let file = escape_xml(&image.to_string_lossy());
+        xml.push_str("  <static>\n");
+        xml.push_str(&format!("    <duration>{}.0</duration>\n", duration));
+        xml.push_str(&format!("    <file>{}</file>\n", file));
+        xml.push_str("  </static>\n");
+
+        if images.len() > 1 && transition > 0 {
+            let next = escape_xml(&images[(i + 1) % images.len()].to_string_lossy());
+            xml.push_str("  <transition type=\"overlay\">\n");
+            xml.push_str(&format!("    <duration>{}.0</duration>\n", transition));
+            xml.push_str(&format!("    <from>{}</from>\n", file));
+            xml.push_str(&format!("    <to>{}</to>\n", next));
+            xml.push_str("  </transition>\n");
+        }
+    }
+
+    xml.push_str("</background>\n");
+    xml
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Import a GNOME slideshow or a macOS dynamic desktop under a name, replacing any earlier one
+// of that name
+pub fn import(path: &Path, name: &str) -> Result<DynamicWallpaper, String> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(format!("'{}' can't be used as a name", name));
+    }
+
+    let dir = dynamic_dir()?.join(name);
+    if dir.exists() {
+        if let Err(e) = fs::remove_dir_all(&dir) {
+            return Err(format!("Failed to replace '{}': {}", dir.display(), e));
+        }
+    }
+    if let Err(e) = fs::create_dir_all(&dir) {
+        return Err(format!("Failed to create '{}': {}", dir.display(), e));
+    }
+
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let imported = match extension.as_str() {
+        "xml" => {
+            let contents = fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read '{}': {}", path.display(), e))?;
+            let base_dir = path.parent().unwrap_or(Path::new(""));
+            parse_gnome_xml(&contents, base_dir)
+                .map(|wallpaper| DynamicWallpaper {
+                    source: path.to_path_buf(),
+                    ..wallpaper
+                })
+                .map_err(|e| format!("Failed to parse '{}': {}", path.display(), e))
+        }
+        #[cfg(feature = "heif")]
+        "heic" | "heif" => heic::import(path, &dir)
+            .map_err(|e| format!("Failed to import '{}': {}", path.display(), e)),
+        #[cfg(not(feature = "heif"))]
+        "heic" | "heif" => Err(format!(
+            "Can't import '{}', this build has no HEIC support (build with --features heif)",
+            path.display()
+        )),
+        _ => Err(format!(
+            "'{}' is neither a GNOME slideshow (.xml) nor a dynamic desktop (.heic)",
+            path.display()
+        )),
+    };
+
+    let wallpaper = match imported {
+        Ok(wallpaper) => wallpaper,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&dir);
+            return Err(e);
+        }
+    };
+
+    wallpaper.save(name)?;
+    Ok(wallpaper)
+}
@@ -0,0 +1,162 @@
+use base64::Engine;
+use chrono::NaiveDateTime;
+use libheif_rs::{HeifContext, ImageHandle, LibHeif};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use super::{DynamicFrame, DynamicWallpaper, SunPosition, DAY};
+use crate::decode;
+
+// Namespace of the XMP attributes macOS keeps the dynamic desktop metadata in
+const APPLE_DESKTOP_NS: &str = "http://ns.apple.com/namespace/1.0/";
+
+// Extract the images of a macOS dynamic desktop into the folder and read when to show them
+pub fn import(path: &Path, frame_dir: &Path) -> Result<DynamicWallpaper, String> {
+    let context =
+        HeifContext::read_from_file(&path.to_string_lossy()).map_err(|e| e.to_string())?;
+    let primary = context.primary_image_handle().map_err(|e| e.to_string())?;
+    let metadata = apple_desktop_metadata(&primary)?;
+
+    let lib_heif = LibHeif::new();
+    let mut images = Vec::new();
+    for (i, handle) in context.top_level_image_handles().iter().enumerate() {
+        let frame = decode::heif_image(&lib_heif, handle)
+            .map_err(|e| format!("failed to decode image {}: {}", i, e))?;
+
+        let target = frame_dir.join(format!("{}.png", i));
+        if let Err(e) = photon_rs::native::save_image(frame, &target) {
+            return Err(format!("Failed to save '{}': {}", target.display(), e));
+        }
+        images.push(target);
+    }
+
+    let image = |index: &plist::Value| -> Result<PathBuf, String> {
+        number(index)
+            .and_then(|i| images.get(i as usize))
+            .cloned()
+            .ok_or_else(|| "the metadata refers to an image that isn't there".to_string())
+    };
+
+    let mut frames = Vec::new();
+    match metadata {
+        AppleDesktop::Solar(entries) => {
+            for entry in entries {
+                let entry = entry.as_dictionary();
+                let value = |key: &str| entry.and_then(|e| e.get(key));
+                let (Some(index), Some(altitude), Some(azimuth)) = (
+                    value("i"),
+                    value("a").and_then(number),
+                    value("z").and_then(number),
+                ) else {
+                    return Err("an entry of the solar metadata is incomplete".to_string());
+                };
+                frames.push(DynamicFrame {
+                    image: image(index)?,
+                    offset: 0,
+                    sun: Some(SunPosition { altitude, azimuth }),
+                });
+            }
+        }
+        AppleDesktop::TimeOfDay(entries) => {
+            for entry in entries {
+                let entry = entry.as_dictionary();
+                let value = |key: &str| entry.and_then(|e| e.get(key));
+                let (Some(index), Some(time)) = (value("i"), value("t").and_then(number)) else {
+                    return Err("an entry of the time metadata is incomplete".to_string());
+                };
+                frames.push(DynamicFrame {
+                    image: image(index)?,
+                    offset: (time.clamp(0.0, 1.0) * DAY as f64) as u64,
+                    sun: None,
+                });
+            }
+            frames.sort_by_key(|frame| frame.offset);
+        }
+    }
+
+    if frames.is_empty() {
+        return Err("the metadata lists no images".to_string());
+    }
+
+    Ok(DynamicWallpaper {
+        source: path.to_path_buf(),
+        anchor: NaiveDateTime::default(),
+        cycle: DAY,
+        frames,
+    })
+}
+
+enum AppleDesktop {
+    // Entries with the sun's altitude "a", azimuth "z" and image index "i"
+    Solar(Vec<plist::Value>),
+    // Entries with the fraction of the day "t" and image index "i"
+    TimeOfDay(Vec<plist::Value>),
+}
+
+// The sequence is a base64 encoded property list in an XMP attribute of the primary image
+fn apple_desktop_metadata(primary: &ImageHandle) -> Result<AppleDesktop, String> {
+    for id in primary.metadata_block_ids(b"mime") {
+        let Ok(block) = primary.metadata(id) else {
+            continue;
+        };
+        let xmp = String::from_utf8_lossy(&block);
+        let Ok(doc) = roxmltree::Document::parse(xmp.trim_end_matches('\0')) else {
+            continue;
+        };
+
+        for node in doc.descendants().filter(|n| n.is_element()) {
+            // Written either as attributes or as elements, depending on the tool that made it
+            let mut values: Vec<(&str, &str)> = node
+                .attributes()
+                .filter(|a| a.namespace() == Some(APPLE_DESKTOP_NS))
+                .map(|a| (a.name(), a.value()))
+                .collect();
+            if node.tag_name().namespace() == Some(APPLE_DESKTOP_NS) {
+                if let Some(text) = node.text() {
+                    values.push((node.tag_name().name(), text));
+                }
+            }
+
+            for (name, value) in values {
+                let list = match name {
+                    "solar" => "si",
+                    "h24" => "ti",
+                    "apr" => {
+                        return Err(
+                            "only has light and dark variants, not a time of day sequence"
+                                .to_string(),
+                        )
+                    }
+                    _ => continue,
+                };
+
+                let bytes = base64::engine::general_purpose::STANDARD
+                    .decode(value.trim())
+                    .map_err(|e| format!("invalid {} metadata: {}", name, e))?;
+                let plist = plist::Value::from_reader(Cursor::new(bytes))
+                    .map_err(|e| format!("invalid {} metadata: {}", name, e))?;
+                let entries = plist
+                    .as_dictionary()
+                    .and_then(|d| d.get(list))
+                    .and_then(|l| l.as_array())
+                    .cloned()
+                    .unwrap_or_default();
+
+                return Ok(match name {
+                    "solar" => AppleDesktop::Solar(entries),
+                    _ => AppleDesktop::TimeOfDay(entries),
+                });
+            }
+        }
+    }
+
+    Err("not a dynamic desktop, it has no time of day metadata".to_string())
+}
+
+// Property lists store whole numbers as integers and the rest as reals
+fn number(value: &plist::Value) -> Option<f64> {
+    value
+        .as_real()
+        .or_else(|| value.as_signed_integer().map(|n| n as f64))
+        .or_else(|| value.as_unsigned_integer().map(|n| n as f64))
+}
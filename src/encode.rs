@@ -0,0 +1,98 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{DynamicImage, RgbaImage};
+use photon_rs::PhotonImage;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::config::OutputFormat;
+use crate::decode::{self, Format};
+
+// Pixels per side of the grid whose colors are counted
+const SAMPLE_GRID: u32 = 64;
+
+// More distinct colors than this among the samples make an image count as a photo
+const PHOTO_COLORS: usize = 512;
+
+// The format to write renderings of the source in. Auto picks JPEG for photos, which it
+// compresses far better, and PNG for flat graphics, whose edges JPEG would smear. The decoded
+// source is only looked at when its type doesn't already tell.
+pub fn resolve(format: OutputFormat, source: &Path, decoded: &PhotonImage) -> OutputFormat {
+    resolve_by_type(format, source).unwrap_or_else(|| {
+        if looks_photographic(decoded) {
+            OutputFormat::Jpeg
+        } else {
+            OutputFormat::Png
+        }
+    })
+}
+
+// What can be told without decoding the source, None when auto has to look at its pixels
+pub fn resolve_by_type(format: OutputFormat, source: &Path) -> Option<OutputFormat> {
+    if format != OutputFormat::Auto {
+        return Some(format);
+    }
+
+    // Formats that can hold either kind of image have to be decoded to tell
+    match decode::sniff_file(source) {
+        Some(Format::Jpeg | Format::Heic | Format::Avif) => Some(OutputFormat::Jpeg),
+        Some(Format::Svg | Format::Gif) => Some(OutputFormat::Png),
+        _ => None,
+    }
+}
+
+// Flat graphics reuse a handful of colors, photos hardly ever repeat one
+pub fn looks_photographic(image: &PhotonImage) -> bool {
+    let (width, height) = (image.get_width(), image.get_height());
+    let (columns, rows) = (SAMPLE_GRID.min(width), SAMPLE_GRID.min(height));
+    let pixels = image.get_raw_pixels();
+
+    let mut colors = HashSet::new();
+    for row in 0..rows {
+        let y = row * height / rows;
+        for column in 0..columns {
+            let x = column * width / columns;
+            let at = ((y * width + x) * 4) as usize;
+            colors.insert([pixels[at], pixels[at + 1], pixels[at + 2]]);
+        }
+    }
+    colors.len() > PHOTO_COLORS
+}
+
+// Write the image in the format its extension names, PNG for unknown ones. The quality, from
+// 1 to 100, only matters for JPEG, which also drops the transparency. WebP is lossless.
+pub fn save(image: PhotonImage, path: &Path, quality: u8) -> Result<(), String> {
+    let (width, height) = (image.get_width(), image.get_height());
+    let Some(rgba) = RgbaImage::from_raw(width, height, image.get_raw_pixels()) else {
+        return Err(format!(
+            "Failed to save '{}': the pixels don't match its size",
+            path.display()
+        ));
+    };
+
+    let file = match File::create(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("Failed to create '{}': {}", path.display(), e)),
+    };
+    let mut writer = BufWriter::new(file);
+
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let written = match extension.as_str() {
+        "jpg" | "jpeg" => DynamicImage::ImageRgba8(rgba)
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut writer, quality)),
+        "webp" => rgba.write_with_encoder(WebPEncoder::new_lossless(&mut writer)),
+        _ => rgba.write_with_encoder(PngEncoder::new(&mut writer)),
+    };
+
+    written
+        .map_err(|e| e.to_string())
+        .and_then(|()| writer.flush().map_err(|e| e.to_string()))
+        .map_err(|e| format!("Failed to save '{}': {}", path.display(), e))
+}
@@ -0,0 +1,404 @@
+use slint::winit_030::{winit, EventResult, WinitWindowAccessor};
+use slint::{
+    FilterModel, Model, ModelRc, Rgba8Pixel, SharedPixelBuffer, SharedString, Timer, TimerMode,
+    VecModel,
+};
+use std::cell::RefCell;
+use std::error::Error;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::{Config, MonitorSettings};
+use crate::logic::{self, ChangeRequest};
+use crate::os_level::{self, MonitorInfo};
+use crate::sources::{self, Orientation};
+use crate::state;
+use crate::thumbnails;
+
+slint::include_modules!();
+
+// Previews are rendered this many times smaller than the monitor
+const PREVIEW_SCALE: usize = 4;
+
+// Space left between monitors in the layout drawing, in monitor pixels
+const LAYOUT_GAP: f32 = 80.0;
+
+// Show the manager window until it is closed
+pub fn run() -> Result<(), Box<dyn Error>> {
+    let ui = AppWindow::new()?;
+
+    let monitors = Rc::new(os_level::get_profile_info()?);
+    let config = Config::load()?;
+    let profile = config
+        .matching_profile(&monitors)
+        .map(|(_, profile)| profile);
+    let settings: Rc<Vec<MonitorSettings>> = Rc::new(
+        monitors
+            .iter()
+            .map(|monitor| config.settings_for(monitor, profile))
+            .collect(),
+    );
+
+    let (items, layout_width, layout_height) = layout_items(&monitors);
+    ui.set_monitors(ModelRc::from(Rc::new(VecModel::from(items))));
+    ui.set_layout_width(layout_width);
+    ui.set_layout_height(layout_height);
+
+    // Start from what is currently shown on each monitor
+    if let Ok(state) = state::State::load() {
+        for (index, monitor) in monitors.iter().enumerate() {
+            let current = state
+                .current
+                .iter()
+                .find(|saved| saved.identity == monitor.identity);
+            if let Some(saved) = current.filter(|saved| saved.path.exists()) {
+                set_monitor_image(&ui, index, saved.path.clone(), monitor, &settings[index]);
+            }
+        }
+    }
+
+    ui.on_choose_image({
+        let ui_handle = ui.as_weak();
+        let monitors = monitors.clone();
+        let settings = settings.clone();
+        move |index| {
+            let ui = ui_handle.unwrap();
+            let index = index as usize;
+            if index >= monitors.len() {
+                return;
+            }
+
+            let file = rfd::FileDialog::new()
+                .add_filter("Images", sources::IMAGE_EXTENSIONS)
+                .pick_file();
+
+            if let Some(path) = file {
+                set_monitor_image(&ui, index, path, &monitors[index], &settings[index]);
+            }
+        }
+    });
+
+    // Files dropped on the window go to the selected monitor
+    ui.window().on_winit_window_event({
+        let ui_handle = ui.as_weak();
+        let monitors = monitors.clone();
+        let settings = settings.clone();
+        move |_window, event| {
+            if let winit::event::WindowEvent::DroppedFile(path) = event {
+                let ui = ui_handle.unwrap();
+                let index = ui.get_selected() as usize;
+                if index < monitors.len() && sources::is_image(path) {
+                    let path = path.clone();
+                    set_monitor_image(&ui, index, path, &monitors[index], &settings[index]);
+                } else {
+                    ui.set_status(format!("'{}' is not an image", path.display()).into());
+                }
+                return EventResult::PreventDefault;
+            }
+            EventResult::Propagate
+        }
+    });
+
+    ui.on_apply({
+        let ui_handle = ui.as_weak();
+        move || {
+            let ui = ui_handle.unwrap();
+
+            let changes: Vec<ChangeRequest> = ui
+                .get_monitors()
+                .iter()
+                .filter(|item| !item.image_path.is_empty())
+                .map(|item| ChangeRequest {
+                    source: Some(PathBuf::from(item.image_path.as_str())),
+                    monitors: vec![item.id],
+                    ..Default::default()
+                })
+                .collect();
+
+            if changes.is_empty() {
+                ui.set_status("Choose an image for at least one monitor first".into());
+                return;
+            }
+
+            apply_in_background(&ui, changes);
+        }
+    });
+
+    let _browser_timer = setup_thumbnail_browser(&ui, monitors.clone(), settings.clone());
+
+    ui.run()?;
+
+    Ok(())
+}
+
+// Rendering full size images takes a while, keep the window responsive
+fn apply_in_background(ui: &AppWindow, changes: Vec<ChangeRequest>) {
+    ui.set_status("Applying...".into());
+
+    let ui_handle = ui.as_weak();
+    thread::spawn(move || {
+        for request in &changes {
+            logic::change(request).print();
+        }
+        let _ = slint::invoke_from_event_loop(move || {
+            if let Some(ui) = ui_handle.upgrade() {
+                ui.set_status("Backgrounds applied".into());
+            }
+        });
+    });
+}
+
+// Sent from the thumbnail worker thread, tagged with the folder load they belong to
+enum BrowserUpdate {
+    Listed(usize, Vec<(PathBuf, Option<Orientation>)>),
+    Thumbnail(usize, usize, PathBuf),
+}
+
+// Browse the configured folder sources. Listing and thumbnail generation happen in a worker
+// thread, the returned timer hands the results over to the UI and has to be kept alive.
+fn setup_thumbnail_browser(
+    ui: &AppWindow,
+    monitors: Rc<Vec<MonitorInfo>>,
+    settings: Rc<Vec<MonitorSettings>>,
+) -> Timer {
+    // Every configured folder, each listed once
+    let mut folders: Vec<PathBuf> = Vec::new();
+    for monitor_settings in settings.iter() {
+        for source in &monitor_settings.sources {
+            if source.is_dir() && !folders.contains(source) {
+                folders.push(source.clone());
+            }
+        }
+    }
+    let folders = Rc::new(folders);
+
+    let folder_names: Vec<SharedString> = folders
+        .iter()
+        .map(|folder| folder.display().to_string().into())
+        .collect();
+    ui.set_sources(ModelRc::new(VecModel::from(folder_names)));
+
+    let monitor_names: Vec<SharedString> = monitors
+        .iter()
+        .map(|monitor| format!("{}: {}", monitor.id, monitor.name).into())
+        .collect();
+    ui.set_monitor_names(ModelRc::new(VecModel::from(monitor_names)));
+
+    let all_items = Rc::new(VecModel::<ThumbnailItem>::default());
+    let orientation_filter = Rc::new(RefCell::new(String::from("All")));
+    let filtered = Rc::new(FilterModel::new(all_items.clone(), {
+        let orientation_filter = orientation_filter.clone();
+        move |item: &ThumbnailItem| {
+            let filter = orientation_filter.borrow();
+            filter.as_str() == "All" || item.orientation.as_str() == filter.as_str()
+        }
+    }));
+    ui.set_thumbnails(ModelRc::from(filtered.clone()));
+
+    let (sender, receiver) = mpsc::channel::<BrowserUpdate>();
+    let generation = Arc::new(AtomicUsize::new(0));
+
+    let load_folder = Rc::new({
+        let all_items = all_items.clone();
+        let generation = generation.clone();
+        let folders = folders.clone();
+        move |index: usize| {
+            let Some(folder) = folders.get(index).cloned() else {
+                return;
+            };
+            all_items.set_vec(Vec::new());
+
+            let current = generation.fetch_add(1, Ordering::SeqCst) + 1;
+            let generation = generation.clone();
+            let sender = sender.clone();
+            thread::spawn(move || {
+                let images: Vec<(PathBuf, Option<Orientation>)> =
+                    sources::collect_images(&[folder])
+                        .into_iter()
+                        .map(|path| {
+                            let orientation = sources::orientation(&path);
+                            (path, orientation)
+                        })
+                        .collect();
+                let paths: Vec<PathBuf> = images.iter().map(|(path, _)| path.clone()).collect();
+
+                if sender.send(BrowserUpdate::Listed(current, images)).is_err() {
+                    return;
+                }
+
+                for (row, path) in paths.iter().enumerate() {
+                    // Stop early when another folder was selected in the meantime
+                    if generation.load(Ordering::SeqCst) != current {
+                        return;
+                    }
+                    match thumbnails::get_or_create(path) {
+                        Ok(thumbnail) => {
+                            let update = BrowserUpdate::Thumbnail(current, row, thumbnail);
+                            if sender.send(update).is_err() {
+                                return;
+                            }
+                        }
+                        Err(e) => eprintln!("{}", e),
+                    }
+                }
+            });
+        }
+    });
+
+    ui.on_source_selected({
+        let load_folder = load_folder.clone();
+        move |index| load_folder(index as usize)
+    });
+
+    ui.on_orientation_selected({
+        let filtered = filtered.clone();
+        move |value| {
+            *orientation_filter.borrow_mut() = value.to_string();
+            filtered.reset();
+        }
+    });
+
+    ui.on_set_on_monitor({
+        let ui_handle = ui.as_weak();
+        move |path, monitor| {
+            let ui = ui_handle.unwrap();
+            let index = monitor as usize;
+            if index >= monitors.len() {
+                return;
+            }
+
+            let path = PathBuf::from(path.as_str());
+            ui.set_selected(monitor);
+            set_monitor_image(&ui, index, path.clone(), &monitors[index], &settings[index]);
+
+            let request = ChangeRequest {
+                source: Some(path),
+                monitors: vec![monitors[index].id as i32],
+                ..Default::default()
+            };
+            apply_in_background(&ui, vec![request]);
+        }
+    });
+
+    let timer = Timer::default();
+    timer.start(TimerMode::Repeated, Duration::from_millis(100), move || {
+        while let Ok(update) = receiver.try_recv() {
+            let current = generation.load(Ordering::SeqCst);
+            match update {
+                BrowserUpdate::Listed(load, images) if load == current => {
+                    let items: Vec<ThumbnailItem> = images
+                        .into_iter()
+                        .map(|(path, orientation)| ThumbnailItem {
+                            name: path
+                                .file_name()
+                                .map(|name| name.to_string_lossy().to_string())
+                                .unwrap_or_default()
+                                .into(),
+                            path: path.to_string_lossy().to_string().into(),
+                            orientation: orientation.map(|o| o.name()).unwrap_or("").into(),
+                            thumbnail: Default::default(),
+                        })
+                        .collect();
+                    all_items.set_vec(items);
+                }
+                BrowserUpdate::Thumbnail(load, row, thumbnail) if load == current => {
+                    if let Some(mut item) = all_items.row_data(row) {
+                        item.thumbnail =
+                            slint::Image::load_from_path(&thumbnail).unwrap_or_default();
+                        all_items.set_row_data(row, item);
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+
+    load_folder(0);
+
+    timer
+}
+
+// MonitorInfo has no position, so the monitors are drawn side by side in order, bottom aligned
+fn layout_items(monitors: &[MonitorInfo]) -> (Vec<MonitorItem>, f32, f32) {
+    let layout_height = monitors.iter().map(|m| m.height).max().unwrap_or(1) as f32;
+
+    let mut x = 0.0;
+    let mut items = Vec::new();
+    for monitor in monitors {
+        items.push(MonitorItem {
+            id: monitor.id as i32,
+            name: monitor.name.clone().into(),
+            resolution: format!("{}x{}", monitor.width, monitor.height).into(),
+            x,
+            y: layout_height - monitor.height as f32,
+            width: monitor.width as f32,
+            height: monitor.height as f32,
+            ..Default::default()
+        });
+        x += monitor.width as f32 + LAYOUT_GAP;
+    }
+
+    let layout_width = (x - LAYOUT_GAP).max(1.0);
+    (items, layout_width, layout_height)
+}
+
+// Select the image for a monitor and render its preview in the background
+fn set_monitor_image(
+    ui: &AppWindow,
+    index: usize,
+    source: PathBuf,
+    monitor: &MonitorInfo,
+    settings: &MonitorSettings,
+) {
+    let model = ui.get_monitors();
+    if let Some(mut item) = model.row_data(index) {
+        item.image_path = source.to_string_lossy().to_string().into();
+        model.set_row_data(index, item);
+    }
+    ui.set_status(format!("Rendering preview of '{}'...", source.display()).into());
+
+    let preview_size = (
+        (monitor.width / PREVIEW_SCALE).max(1) as u32,
+        (monitor.height / PREVIEW_SCALE).max(1) as u32,
+    );
+    let settings = settings.scaled(1.0 / PREVIEW_SCALE as f32);
+    let ui_handle = ui.as_weak();
+
+    thread::spawn(move || {
+        let result = logic::render_image(&source, preview_size, &settings).map(|image| {
+            SharedPixelBuffer::<Rgba8Pixel>::clone_from_slice(
+                &image.get_raw_pixels(),
+                image.get_width(),
+                image.get_height(),
+            )
+        });
+
+        let _ = slint::invoke_from_event_loop(move || {
+            let Some(ui) = ui_handle.upgrade() else {
+                return;
+            };
+            let model = ui.get_monitors();
+            let Some(mut item) = model.row_data(index) else {
+                return;
+            };
+
+            // Another image may have been chosen while this one was rendering
+            if item.image_path.as_str() != source.to_string_lossy() {
+                return;
+            }
+
+            match result {
+                Ok(buffer) => {
+                    item.preview = slint::Image::from_rgba8(buffer);
+                    model.set_row_data(index, item);
+                    ui.set_status("".into());
+                }
+                Err(e) => ui.set_status(e.into()),
+            }
+        });
+    });
+}
@@ -0,0 +1,127 @@
+use std::io;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::HookConfig;
+
+// How often a running hook is checked for having finished
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Run the command of a hook through the shell with the given environment variables, waiting
+// at most its timeout. Its output goes to stderr so it can't mix with JSON on stdout.
+pub fn run(hook: &HookConfig, vars: &[(&str, String)]) -> Result<(), String> {
+    let mut command = shell_command(&hook.command);
+    command
+        .envs(vars.iter().map(|(name, value)| (name, value)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::from(io::stderr()))
+        .stderr(Stdio::inherit());
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("Hook '{}' could not be started: {}", hook.command, e))?;
+
+    let timeout = hook.timeout();
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => {
+                return Err(format!("Hook '{}' failed with {}", hook.command, status));
+            }
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!(
+                    "Hook '{}' timed out after {} second(s)",
+                    hook.command,
+                    timeout.as_secs()
+                ));
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(e) => return Err(format!("Hook '{}' failed: {}", hook.command, e)),
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn shell_command(line: &str) -> Command {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    let mut command = Command::new("cmd");
+    command.arg("/C").raw_arg(line);
+    command.creation_flags(CREATE_NO_WINDOW);
+    command
+}
+
+#[cfg(not(target_os = "windows"))]
+fn shell_command(line: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(line);
+    command
+}
+
+#[cfg(all(test, not(target_os = "windows")))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    // Write a stub script to a file of its own and make a hook that runs it with sh
+    fn stub(name: &str, script: &str, timeout: Option<&str>) -> (HookConfig, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("background_hooks_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.sh", name));
+        fs::write(&path, script).unwrap();
+
+        let hook = HookConfig {
+            command: format!("sh '{}'", path.display()),
+            timeout: timeout.map(str::to_string),
+            monitors: None,
+        };
+        (hook, dir)
+    }
+
+    #[test]
+    fn exit_zero_is_ok() {
+        let (hook, _) = stub("exit_zero", "exit 0\n", None);
+        assert_eq!(run(&hook, &[]), Ok(()));
+    }
+
+    #[test]
+    fn non_zero_exit_is_a_failure() {
+        let (hook, _) = stub("exit_three", "exit 3\n", None);
+        let error = run(&hook, &[]).unwrap_err();
+        assert!(error.contains("failed"), "{}", error);
+        assert!(error.contains('3'), "{}", error);
+    }
+
+    #[test]
+    fn hook_past_its_timeout_is_killed() {
+        let (hook, _) = stub("sleep", "sleep 30\n", Some("1s"));
+        let started = Instant::now();
+        let error = run(&hook, &[]).unwrap_err();
+        assert!(error.contains("timed out after 1 second(s)"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn variables_reach_the_script() {
+        let (hook, dir) = stub(
+            "env",
+            "printf '%s %s' \"$BACKGROUND_MONITOR\" \"$BACKGROUND_SOURCE\" > \"$(dirname \"$0\")/env.out\"\n",
+            None,
+        );
+        let vars = [
+            ("BACKGROUND_MONITOR", "2".to_string()),
+            ("BACKGROUND_SOURCE", "/photos/a b.jpg".to_string()),
+        ];
+        assert_eq!(run(&hook, &vars), Ok(()));
+        assert_eq!(
+            fs::read_to_string(dir.join("env.out")).unwrap(),
+            "2 /photos/a b.jpg"
+        );
+    }
+}
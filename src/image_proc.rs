@@ -1,4 +1,286 @@
+use ab_glyph::{point, Font, FontVec, Glyph, GlyphId, Point, PxScale, PxScaleFont, ScaleFont};
 use photon_rs::PhotonImage;
+use std::str::FromStr;
+
+use crate::os_level::Insets;
+
+// Shadows behind overlay text are black and partly see-through
+const SHADOW_COLOR: (u8, u8, u8) = (0, 0, 0);
+const SHADOW_OPACITY: f32 = 0.6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    // Whole image visible, centered on the backdrop
+    Fit,
+    // Cover the screen, cropping what does not fit
+    Fill,
+    // Cover the screen, ignoring the aspect ratio
+    Stretch,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fit" => Ok(Mode::Fit),
+            "fill" => Ok(Mode::Fill),
+            "stretch" => Ok(Mode::Stretch),
+            _ => Err(format!(
+                "unknown mode '{}', expected one of: fit, fill, stretch",
+                s
+            )),
+        }
+    }
+}
+
+// What is shown around a fitted image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backdrop {
+    Blur,
+    Color(u8, u8, u8),
+}
+
+impl FromStr for Backdrop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "blur" {
+            return Ok(Backdrop::Blur);
+        }
+
+        match parse_color(s) {
+            Some((r, g, b)) => Ok(Backdrop::Color(r, g, b)),
+            None => Err(format!(
+                "unknown backdrop '{}', expected 'blur' or a color like '#1e1e2e'",
+                s
+            )),
+        }
+    }
+}
+
+// Accepts colors like "#1e1e2e"
+pub fn parse_color(s: &str) -> Option<(u8, u8, u8)> {
+    let hex = s.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+    Some((channel(0), channel(2), channel(4)))
+}
+
+// Where on the screen a block of text is placed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl FromStr for Anchor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "top-left" => Ok(Anchor::TopLeft),
+            "top" => Ok(Anchor::Top),
+            "top-right" => Ok(Anchor::TopRight),
+            "left" => Ok(Anchor::Left),
+            "center" => Ok(Anchor::Center),
+            "right" => Ok(Anchor::Right),
+            "bottom-left" => Ok(Anchor::BottomLeft),
+            "bottom" => Ok(Anchor::Bottom),
+            "bottom-right" => Ok(Anchor::BottomRight),
+            _ => Err(format!(
+                "unknown anchor '{}', expected one of: top-left, top, top-right, left, center, \
+                 right, bottom-left, bottom, bottom-right",
+                s
+            )),
+        }
+    }
+}
+
+impl Anchor {
+    // Horizontal and vertical position as 0 for the start, 0.5 for the middle and 1 for the end
+    fn fractions(&self) -> (f32, f32) {
+        match self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        }
+    }
+}
+
+pub struct TextStyle {
+    pub font: FontVec,
+    // Pixel height of a line
+    pub size: f32,
+    pub color: (u8, u8, u8),
+    // Draw a dark copy slightly offset behind the text so it stays readable on light images
+    pub shadow: bool,
+    pub anchor: Anchor,
+    // Distance kept from the edges of the visible area
+    pub margin: f32,
+    pub insets: Insets,
+}
+
+// Draw rows of text as one block. Rows with a single cell are lines aligned towards the anchor;
+// rows with several cells share equally wide, right-aligned columns, which keeps a calendar
+// lined up with any font.
+pub fn draw_text(image: &mut PhotonImage, rows: &[Vec<String>], style: &TextStyle) {
+    let font = style.font.as_scaled(PxScale::from(style.size));
+    let (width, height) = (image.get_width(), image.get_height());
+
+    let text_width = |text: &str| place_glyphs(&font, text, point(0.0, 0.0)).1;
+
+    let gap = style.size * 0.4;
+    let column_width = rows
+        .iter()
+        .filter(|row| row.len() > 1)
+        .flatten()
+        .map(|cell| text_width(cell))
+        .fold(0.0, f32::max)
+        + gap;
+    let row_width = |row: &[String]| match row {
+        [] => 0.0,
+        [line] => text_width(line),
+        cells => cells.len() as f32 * column_width - gap,
+    };
+
+    let line_height = font.height() + font.line_gap();
+    let block_width = rows.iter().map(|row| row_width(row)).fold(0.0, f32::max);
+    let block_height = rows.len() as f32 * line_height;
+
+    let (fx, fy) = style.anchor.fractions();
+    let (area_x, area_y, area_width, area_height) = style.insets.visible_area((width, height));
+    let place = |start: u32, space: u32, block: f32, fraction: f32| {
+        start as f32 + style.margin + (space as f32 - 2.0 * style.margin - block) * fraction
+    };
+    let left = place(area_x, area_width, block_width, fx);
+    let top = place(area_y, area_height, block_height, fy);
+
+    let mut pixels = image.get_raw_pixels();
+    let shadow_offset = (style.size / 16.0).max(1.0);
+    let mut passes = Vec::new();
+    if style.shadow {
+        passes.push((shadow_offset, SHADOW_COLOR, SHADOW_OPACITY));
+    }
+    passes.push((0.0, style.color, 1.0));
+
+    for (offset, color, opacity) in passes {
+        for (i, row) in rows.iter().enumerate() {
+            let baseline = top + i as f32 * line_height + font.ascent() + offset;
+            let start = left + (block_width - row_width(row)) * fx + offset;
+            let mut draw = |text: &str, x: f32| {
+                for glyph in place_glyphs(&font, text, point(x, baseline)).0 {
+                    let Some(outlined) = style.font.outline_glyph(glyph) else {
+                        continue;
+                    };
+                    let bounds = outlined.px_bounds();
+                    outlined.draw(|gx, gy, coverage| {
+                        let px = bounds.min.x as i64 + gx as i64;
+                        let py = bounds.min.y as i64 + gy as i64;
+                        if px < 0 || py < 0 || px >= width as i64 || py >= height as i64 {
+                            return;
+                        }
+                        let at = ((py as u32 * width + px as u32) * 4) as usize;
+                        let alpha = coverage.clamp(0.0, 1.0) * opacity;
+                        for (channel, value) in pixels[at..at + 3]
+                            .iter_mut()
+                            .zip([color.0, color.1, color.2])
+                        {
+                            *channel = (*channel as f32 * (1.0 - alpha) + value as f32 * alpha)
+                                .round() as u8;
+                        }
+                    });
+                }
+            };
+
+            match row.as_slice() {
+                [] => {}
+                [line] => draw(line, start),
+                cells => {
+                    for (j, cell) in cells.iter().enumerate() {
+                        let right = start + (j + 1) as f32 * column_width - gap;
+                        draw(cell, right - text_width(cell));
+                    }
+                }
+            }
+        }
+    }
+
+    *image = PhotonImage::new(pixels, width, height);
+}
+
+// Lay out a line of text from the origin on its baseline, returning its glyphs and width
+fn place_glyphs(font: &PxScaleFont<&FontVec>, text: &str, origin: Point) -> (Vec<Glyph>, f32) {
+    let mut glyphs = Vec::new();
+    let mut previous: Option<GlyphId> = None;
+    let mut caret = origin.x;
+    for c in text.chars() {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += font.kern(previous, id);
+        }
+        glyphs.push(id.with_scale_and_position(font.scale(), point(caret, origin.y)));
+        caret += font.h_advance(id);
+        previous = Some(id);
+    }
+    (glyphs, caret - origin.x)
+}
+
+// Scale every color towards black. An amount of 0.4 takes away 40% of the brightness.
+pub fn darken(image: &mut PhotonImage, amount: f64) {
+    let factor = (1.0 - amount).clamp(0.0, 1.0);
+    let mut pixels = image.get_raw_pixels();
+    for pixel in pixels.chunks_mut(4) {
+        for channel in &mut pixel[..3] {
+            *channel = (*channel as f64 * factor).round() as u8;
+        }
+    }
+    *image = PhotonImage::new(pixels, image.get_width(), image.get_height());
+}
+
+// The image covers or fits the screen, but is kept centered on the part the insets leave
+// visible so its subject doesn't end up behind a panel or dock
+pub fn compose(
+    image: &PhotonImage,
+    screen_size: (u32, u32),
+    mode: Mode,
+    backdrop: Backdrop,
+    insets: Insets,
+) -> PhotonImage {
+    let (_, _, area_width, area_height) = insets.visible_area(screen_size);
+    match mode {
+        Mode::Fill => fill_to_area(image, screen_size, insets),
+        Mode::Stretch => stretch_to_size(image, screen_size),
+        Mode::Fit => {
+            let fit_img = fit_to_size(image, (area_width, area_height));
+            match backdrop {
+                Backdrop::Blur => {
+                    let fill_img = fill_to_size(image, screen_size);
+                    combine_fit_and_fill(&fit_img, &fill_img, screen_size, insets)
+                }
+                Backdrop::Color(r, g, b) => {
+                    combine_fit_and_color(&fit_img, (r, g, b), screen_size, insets)
+                }
+            }
+        }
+    }
+}
 
 pub fn fit_to_size(image: &PhotonImage, screen_size: (u32, u32)) -> PhotonImage {
     let img_width = image.get_width() as f64;
@@ -13,7 +295,7 @@ pub fn fit_to_size(image: &PhotonImage, screen_size: (u32, u32)) -> PhotonImage
     let fit_height = (img_height * fit_scale) as u32;
 
     photon_rs::transform::resize(
-        &image,
+        image,
         fit_width,
         fit_height,
         photon_rs::transform::SamplingFilter::Lanczos3,
@@ -21,6 +303,12 @@ pub fn fit_to_size(image: &PhotonImage, screen_size: (u32, u32)) -> PhotonImage
 }
 
 pub fn fill_to_size(image: &PhotonImage, screen_size: (u32, u32)) -> PhotonImage {
+    fill_to_area(image, screen_size, Insets::default())
+}
+
+// Cover the whole screen, cropping so the middle of the image comes as close as it can to the
+// middle of the visible area
+pub fn fill_to_area(image: &PhotonImage, screen_size: (u32, u32), insets: Insets) -> PhotonImage {
     let img_width = image.get_width() as f64;
     let img_height = image.get_height() as f64;
 
@@ -39,12 +327,16 @@ pub fn fill_to_size(image: &PhotonImage, screen_size: (u32, u32)) -> PhotonImage
         photon_rs::transform::SamplingFilter::Lanczos3,
     );
 
-    // Calculate center crop coordinates
-    let center_x = fill_width as i32 / 2;
-    let center_y = fill_height as i32 / 2;
+    // Line the center of the image up with the center of the visible area, without moving an
+    // edge of the image onto the screen
+    let (area_x, area_y, area_width, area_height) = insets.visible_area(screen_size);
+    let area_center_x = (area_x + area_width / 2) as i32;
+    let area_center_y = (area_y + area_height / 2) as i32;
 
-    let top_left_x = center_x - (screen_width as i32 / 2);
-    let top_left_y = center_y - (screen_height as i32 / 2);
+    let top_left_x = (fill_width as i32 / 2 - area_center_x)
+        .clamp(0, (fill_width as i32 - screen_width as i32).max(0));
+    let top_left_y = (fill_height as i32 / 2 - area_center_y)
+        .clamp(0, (fill_height as i32 - screen_height as i32).max(0));
 
     let bottom_right_x = top_left_x + screen_width as i32;
     let bottom_right_y = top_left_y + screen_height as i32;
@@ -59,10 +351,20 @@ pub fn fill_to_size(image: &PhotonImage, screen_size: (u32, u32)) -> PhotonImage
     )
 }
 
+pub fn stretch_to_size(image: &PhotonImage, screen_size: (u32, u32)) -> PhotonImage {
+    photon_rs::transform::resize(
+        image,
+        screen_size.0,
+        screen_size.1,
+        photon_rs::transform::SamplingFilter::Lanczos3,
+    )
+}
+
 pub fn combine_fit_and_fill(
     fit_img: &PhotonImage,
     fill_img: &PhotonImage,
     screen_size: (u32, u32),
+    insets: Insets,
 ) -> PhotonImage {
     let mut fill_blur = fill_img.clone();
     let (screen_width, screen_height) = screen_size;
@@ -71,11 +373,35 @@ pub fn combine_fit_and_fill(
         (screen_width.max(screen_height) as f32 / 40.0) as i32,
     );
     // Paste the fit image centered on top of the blurred fill image
-    let fit_width = fit_img.get_width();
-    let fit_height = fit_img.get_height();
-    let paste_x = (screen_width as u32 - fit_width) / 2;
-    let paste_y = (screen_height as u32 - fit_height) / 2;
-    photon_rs::multiple::watermark(&mut fill_blur, &fit_img, paste_x.into(), paste_y.into());
+    let (paste_x, paste_y) = centered_in_area(fit_img, screen_size, insets);
+    photon_rs::multiple::watermark(&mut fill_blur, fit_img, paste_x.into(), paste_y.into());
 
     fill_blur
 }
+
+pub fn combine_fit_and_color(
+    fit_img: &PhotonImage,
+    color: (u8, u8, u8),
+    screen_size: (u32, u32),
+    insets: Insets,
+) -> PhotonImage {
+    let (screen_width, screen_height) = screen_size;
+    let (r, g, b) = color;
+    let pixels = [r, g, b, 255].repeat((screen_width * screen_height) as usize);
+    let mut background = PhotonImage::new(pixels, screen_width, screen_height);
+
+    // Paste the fit image centered on top of the solid background
+    let (paste_x, paste_y) = centered_in_area(fit_img, screen_size, insets);
+    photon_rs::multiple::watermark(&mut background, fit_img, paste_x.into(), paste_y.into());
+
+    background
+}
+
+// Where to paste an image to center it on the visible area
+fn centered_in_area(image: &PhotonImage, screen_size: (u32, u32), insets: Insets) -> (u32, u32) {
+    let (area_x, area_y, area_width, area_height) = insets.visible_area(screen_size);
+    (
+        area_x + area_width.saturating_sub(image.get_width()) / 2,
+        area_y + area_height.saturating_sub(image.get_height()) / 2,
+    )
+}
@@ -0,0 +1,24 @@
+pub mod config;
+pub mod decode;
+pub mod dedupe;
+pub mod dynamic;
+pub mod encode;
+pub mod gui;
+pub mod hooks;
+pub mod image_proc;
+pub mod library;
+pub mod logic;
+pub mod os_level;
+pub mod overlay;
+pub mod palette;
+pub mod photos;
+pub mod ratings;
+pub mod remote;
+pub mod schedule;
+pub mod sources;
+pub mod state;
+pub mod thumbnails;
+// tray-item only works elsewhere with system libraries this crate doesn't link
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+pub mod tray;
+pub mod watcher;
@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::state;
+
+// Images the tool collected itself, with where each one came from. The index is stored as
+// JSON next to the state, the images in the `library` folder of the user data directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Library {
+    #[serde(default)]
+    pub entries: Vec<LibraryEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryEntry {
+    pub path: PathBuf,
+    // The provider or import that added the image, e.g. "bing".
+    pub provider: String,
+    #[serde(default)]
+    pub title: String,
+    // Who to credit for the image, as given by the provider.
+    #[serde(default)]
+    pub attribution: String,
+    // Where the image was downloaded from.
+    #[serde(default)]
+    pub source_url: String,
+    // A page describing the image, when the provider has one.
+    #[serde(default)]
+    pub page_url: Option<String>,
+    // The date the provider published the image, as it gave it.
+    #[serde(default)]
+    pub date: Option<String>,
+    // Seconds since the UNIX epoch.
+    #[serde(default)]
+    pub added: u64,
+}
+
+pub fn library_dir() -> Result<PathBuf, String> {
+    let dir = state::data_dir()?.join("library");
+
+    if let Err(e) = fs::create_dir_all(&dir) {
+        return Err(format!("Failed to create '{}': {}", dir.display(), e));
+    }
+
+    Ok(dir)
+}
+
+fn index_path() -> Result<PathBuf, String> {
+    Ok(state::data_dir()?.join("library.json"))
+}
+
+impl Library {
+    pub fn load() -> Result<Library, String> {
+        let path = index_path()?;
+        if !path.exists() {
+            return Ok(Library::default());
+        }
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => return Err(format!("Failed to read '{}': {}", path.display(), e)),
+        };
+
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse '{}': {}", path.display(), e))
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = index_path()?;
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize the library: {}", e))?;
+
+        fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write '{}': {}", path.display(), e))
+    }
+
+    pub fn find_by_url(&self, url: &str) -> Option<&LibraryEntry> {
+        self.entries.iter().find(|entry| entry.source_url == url)
+    }
+
+    pub fn find_by_path(&self, path: &Path) -> Option<&LibraryEntry> {
+        self.entries.iter().find(|entry| entry.path == path)
+    }
+
+    // Adds the entry, replacing an earlier one for the same file.
+    pub fn add(&mut self, entry: LibraryEntry) {
+        self.entries.retain(|existing| existing.path != entry.path);
+        self.entries.push(entry);
+    }
+}
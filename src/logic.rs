@@ -1,226 +1,2248 @@
+use photon_rs::PhotonImage;
+use serde::Serialize;
+use std::env;
 use std::fs;
-use std::path::PathBuf;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use crate::image_proc;
-use crate::os_level;
+use crate::config::{self, Config, DarkVariant, MonitorSettings, Profile, RemoteConfig};
+use crate::decode;
+use crate::dedupe::{self, HashIndex};
+use crate::dynamic::{self, DynamicWallpaper};
+use crate::encode;
+use crate::hooks;
+use crate::image_proc::{self, Backdrop, Mode};
+use crate::library::{Library, LibraryEntry};
+use crate::os_level::{self, Insets, MonitorInfo};
+use crate::overlay;
+use crate::palette;
+use crate::photos::{self, PhotoIndex};
+use crate::ratings::{self, ImageRating, Ratings};
+use crate::remote::{self, UreqClient};
+use crate::schedule::{self, ScheduleChange, ScheduleTracker};
+use crate::sources;
+use crate::state::{self, SavedBackground};
+use crate::watcher::{DisplayChange, DisplayWatcher};
 
-pub fn change_background(args: &[String]) {
-    // Check if we have the required file parameter
-    if args.is_empty() {
-        eprintln!("Error: -change requires a file name parameter");
-        eprintln!("Usage:");
-        eprintln!("  background_manager -change <file>           Set image on all monitors");
-        eprintln!("  background_manager -change <file> <monitor> Set image on specific monitor");
+// Layout previews are rendered this many times smaller than the monitors
+const LAYOUT_SCALE: u32 = 4;
+
+// Space left between monitors in layout previews, in monitor pixels
+const LAYOUT_GAP: u32 = 80;
+
+const LAYOUT_BACKGROUND: (u8, u8, u8) = (0x20, 0x20, 0x20);
+
+// Crossfades set this many in-between images, this far apart
+const CROSSFADE_FRAMES: u32 = 6;
+const CROSSFADE_STEP: Duration = Duration::from_millis(250);
+const CROSSFADE_QUALITY: u8 = 85;
+
+// What to do with a rendered image
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Apply {
+    Set,
+    // Blend from the background shown now into the new one
+    Crossfade,
+    // Only report where it would be rendered
+    DryRun,
+}
+
+// What happened on one monitor, printed as text or as JSON for scripts
+#[derive(Debug, Default, Serialize)]
+pub struct MonitorResult {
+    pub monitor: i32,
+    pub name: String,
+    pub identity: String,
+    // The image the background is rendered from
+    pub source: Option<PathBuf>,
+    // Where the rendered image was, or on a dry run would be, written. A dry run leaves out the
+    // extension when the auto format would have to decode the source to decide it.
+    pub rendered: Option<PathBuf>,
+    // The variant rendered for dark mode, on systems that switch to it by themselves
+    pub rendered_dark: Option<PathBuf>,
+    // Whether the rendered image was set as background
+    pub applied: bool,
+    pub error: Option<String>,
+    // Hooks that failed after the background was set, which doesn't undo it
+    pub hook_errors: Vec<String>,
+}
+
+impl MonitorResult {
+    fn new(monitor: &MonitorInfo) -> MonitorResult {
+        MonitorResult {
+            monitor: monitor.id as i32,
+            name: monitor.name.clone(),
+            identity: monitor.identity.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn print(&self, dry_run: bool) {
+        if let Some(e) = &self.error {
+            eprintln!("  Monitor {} - {}", self.monitor, e);
+            return;
+        }
+        for e in &self.hook_errors {
+            eprintln!("  Monitor {} - {}", self.monitor, e);
+        }
+
+        let source = display_path(&self.source);
+        let rendered = display_path(&self.rendered);
+        if self.applied {
+            println!(
+                "  Monitor {} - Background set from '{}'",
+                self.monitor, source
+            );
+            if let Some(dark) = &self.rendered_dark {
+                println!(
+                    "  Monitor {} - Dark mode background rendered to '{}'",
+                    self.monitor,
+                    dark.display()
+                );
+            }
+        } else if dry_run {
+            println!(
+                "  Monitor {} - Would render '{}' to '{}'",
+                self.monitor, source, rendered
+            );
+        } else {
+            println!(
+                "  Monitor {} - Rendered '{}' to '{}'",
+                self.monitor, source, rendered
+            );
+        }
+    }
+}
+
+// The outcome of `change` or `preview`. An error that stopped the whole command is in `error`,
+// those of a single monitor are in its result.
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub dry_run: bool,
+    pub profile: Option<String>,
+    pub monitors: Vec<MonitorResult>,
+    // Backgrounds saved because this was the first change
+    pub original_saved: Vec<SavedBackground>,
+    pub error: Option<String>,
+}
+
+impl Report {
+    fn failed(error: String) -> Report {
+        Report {
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none() && self.monitors.iter().all(|result| result.error.is_none())
+    }
+
+    pub fn print(&self) {
+        if let Some(e) = &self.error {
+            eprintln!("Error: {}", e);
+            return;
+        }
+
+        if let Some(profile) = &self.profile {
+            println!("Using profile '{}'", profile);
+        }
+        print_original_saved(&self.original_saved);
+        for result in &self.monitors {
+            result.print(self.dry_run);
+        }
+    }
+
+    pub fn print_json(&self) {
+        print_json(self);
+    }
+}
+
+fn display_path(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default()
+}
+
+fn print_original_saved(saved: &[SavedBackground]) {
+    for saved in saved {
+        println!(
+            "  Monitor {} - Saved original background to '{}'",
+            saved.monitor,
+            saved.path.display()
+        );
+    }
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{}", json),
+        Err(e) => eprintln!("Error: Failed to serialize the output: {}", e),
+    }
+}
+
+// What to show on which monitors. Anything left out comes from the configuration.
+#[derive(Debug, Clone, Default)]
+pub struct ChangeRequest {
+    // An image, or a folder to pick from, instead of the configured sources
+    pub source: Option<PathBuf>,
+    // Monitor numbers, all monitors when empty
+    pub monitors: Vec<i32>,
+    pub mode: Option<Mode>,
+    pub backdrop: Option<Backdrop>,
+    // Only pick the images and report where they would be rendered
+    pub dry_run: bool,
+}
+
+impl ChangeRequest {
+    // The configured settings of a monitor with the overrides of this request on top
+    fn settings_for(
+        &self,
+        config: &Config,
+        monitor: &MonitorInfo,
+        profile: Option<&Profile>,
+    ) -> MonitorSettings {
+        let mut settings = config.settings_for(monitor, profile);
+        if let Some(mode) = self.mode {
+            settings.mode = mode;
+        }
+        if let Some(backdrop) = self.backdrop {
+            settings.backdrop = backdrop;
+        }
+        settings
+    }
+}
+
+// Render and set the background of the requested monitors
+pub fn change(request: &ChangeRequest) -> Report {
+    let (config, monitors) = match load_config_and_monitors() {
+        Ok(loaded) => loaded,
+        Err(e) => return Report::failed(e),
+    };
+    if let Err(e) = check_source(request.source.as_ref()) {
+        return Report::failed(e);
+    }
+    let targets = match select_monitors(&monitors, &request.monitors) {
+        Ok(targets) => targets,
+        Err(e) => return Report::failed(e),
+    };
+
+    let profile = config.matching_profile(&monitors);
+    let mut report = Report {
+        dry_run: request.dry_run,
+        profile: profile.map(|(name, _)| name.to_string()),
+        ..Default::default()
+    };
+    let profile = profile.map(|(_, profile)| profile);
+
+    // Keep a copy of whatever the user had before we touch anything
+    if !request.dry_run {
+        match state::snapshot_original() {
+            Ok(saved) => report.original_saved = saved,
+            Err(e) => eprintln!("Warning: Could not save the original background: {}", e),
+        }
+    }
+
+    let apply = if request.dry_run {
+        Apply::DryRun
+    } else {
+        Apply::Set
+    };
+    for monitor in targets {
+        let settings = request.settings_for(&config, monitor, profile);
+        report.monitors.push(apply_to_monitor(
+            monitor,
+            request.source.as_ref(),
+            &settings,
+            apply,
+        ));
+    }
+
+    report
+}
+
+// Show another image from the configured sources, on one monitor or on all of them
+pub fn next_background(monitor_num: Option<i32>) {
+    rotate(monitor_num, Apply::Set).print();
+}
+
+fn rotate(monitor_num: Option<i32>, apply: Apply) -> Report {
+    let (config, monitors) = match load_config_and_monitors() {
+        Ok(loaded) => loaded,
+        Err(e) => return Report::failed(e),
+    };
+    let targets = match select_monitors(&monitors, monitor_num.as_slice()) {
+        Ok(targets) => targets,
+        Err(e) => return Report::failed(e),
+    };
+
+    let mut report = Report::default();
+    match state::snapshot_original() {
+        Ok(saved) => report.original_saved = saved,
+        Err(e) => eprintln!("Warning: Could not save the original background: {}", e),
+    }
+
+    let profile = config
+        .matching_profile(&monitors)
+        .map(|(_, profile)| profile);
+    let current = state::State::load()
+        .map(|state| state.current)
+        .unwrap_or_default();
+
+    for monitor in targets {
+        let settings = config.settings_for(monitor, profile);
+        let shown = current
+            .iter()
+            .find(|saved| saved.identity == monitor.identity)
+            .map(|saved| saved.path.clone());
+
+        // Don't pick the image that is already shown, unless it is the only one
+        let mut images = collect_for_rotation(&settings.sources, &settings);
+        if images.len() > 1 {
+            if let Some(shown) = &shown {
+                images.retain(|path| path.canonicalize().ok().as_ref() != Some(shown));
+            }
+        }
+
+        let image = pick_for_rotation(&images, monitor, &settings);
+        report
+            .monitors
+            .push(apply_to_monitor(monitor, image.as_ref(), &settings, apply));
+    }
+
+    report
+}
+
+// Switch the monitors whose schedule rule, dynamic wallpaper frame or darkening changed since
+// the last check. The first check only notes what is active.
+pub fn check_schedule(tracker: &mut ScheduleTracker) {
+    let (config, monitors) = match load_config_and_monitors() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+
+    let apply = if config.crossfade() {
+        Apply::Crossfade
+    } else {
+        Apply::Set
+    };
+    for change in tracker.poll(&config, &monitors) {
+        match change {
+            ScheduleChange::Sources(monitor) => {
+                println!("Monitor {} - Schedule changed", monitor.id);
+                rotate(Some(monitor.id as i32), apply).print();
+            }
+            ScheduleChange::Rendering(monitor) => {
+                println!("Monitor {} - Appearance changed", monitor.id);
+                rerender(&config, &monitors, &monitor, apply);
+            }
+        }
+    }
+}
+
+// Render the image shown on a monitor again with its current settings
+fn rerender(config: &Config, monitors: &[MonitorInfo], monitor: &MonitorInfo, apply: Apply) {
+    let source = state::State::load()
+        .ok()
+        .and_then(|state| {
+            state
+                .current
+                .into_iter()
+                .find(|saved| saved.identity == monitor.identity)
+        })
+        .map(|saved| saved.path)
+        .filter(|path| path.exists());
+
+    let profile = config
+        .matching_profile(monitors)
+        .map(|(_, profile)| profile);
+    let settings = config.settings_for(monitor, profile);
+    apply_to_monitor(monitor, source.as_ref(), &settings, apply).print(false);
+}
+
+// Go back to the image shown before the current one, on one monitor or on all of them
+pub fn previous_background(monitor_num: Option<i32>) {
+    let (config, monitors) = match load_config_and_monitors() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    let targets = match select_monitors(&monitors, monitor_num.as_slice()) {
+        Ok(targets) => targets,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+
+    let profile = config
+        .matching_profile(&monitors)
+        .map(|(_, profile)| profile);
+
+    for monitor in targets {
+        let desktop_num = monitor.id as i32;
+        let previous = match state::pop_history(monitor) {
+            Ok(Some(path)) => path,
+            Ok(None) => {
+                println!(
+                    "  Monitor {} - No earlier background to go back to",
+                    desktop_num
+                );
+                continue;
+            }
+            Err(e) => {
+                eprintln!("  Monitor {} - {}", desktop_num, e);
+                continue;
+            }
+        };
+
+        if !previous.exists() {
+            eprintln!(
+                "  Monitor {} - Earlier background '{}' no longer exists",
+                desktop_num,
+                previous.display()
+            );
+            continue;
+        }
+
+        let settings = config.settings_for(monitor, profile);
+        apply_to_monitor(monitor, Some(&previous), &settings, Apply::Set).print(false);
+    }
+}
+
+fn load_config_and_monitors() -> Result<(Config, Vec<MonitorInfo>), String> {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => return Err(format!("Invalid configuration {}", e)),
+    };
+
+    Ok((config, os_level::get_profile_info()?))
+}
+
+// Numbered in the order given and named after their size
+fn monitors_of_size(sizes: &[(usize, usize)]) -> Vec<MonitorInfo> {
+    sizes
+        .iter()
+        .enumerate()
+        .map(|(i, &(width, height))| MonitorInfo {
+            name: format!("{}x{}", width, height),
+            identity: format!("preview-{}", i + 1),
+            id: i + 1,
+            width,
+            height,
+            insets: Insets::default(),
+        })
+        .collect()
+}
+
+// An explicit file or folder to pick from has to exist
+fn check_source(source: Option<&PathBuf>) -> Result<(), String> {
+    match source {
+        Some(path) if !path.exists() => Err(format!("File '{}' does not exist", path.display())),
+        _ => Ok(()),
+    }
+}
+
+// The given monitors, or all of them when none are given
+fn select_monitors<'a>(
+    monitors: &'a [MonitorInfo],
+    monitor_nums: &[i32],
+) -> Result<Vec<&'a MonitorInfo>, String> {
+    if monitor_nums.is_empty() {
+        return Ok(monitors.iter().collect());
+    }
+
+    let mut selected = Vec::new();
+    for &num in monitor_nums {
+        if num < 1 || num as usize > monitors.len() {
+            return Err(format!(
+                "Monitor number must be between 1 and {}",
+                monitors.len()
+            ));
+        }
+        selected.push(&monitors[(num - 1) as usize]);
+    }
+    Ok(selected)
+}
+
+// The given monitor, which may only be left out when there is just one
+fn select_one_monitor(
+    monitors: &[MonitorInfo],
+    monitor_num: Option<i32>,
+) -> Result<&MonitorInfo, String> {
+    match (monitor_num, monitors.len()) {
+        (None, 1) => Ok(&monitors[0]),
+        (None, _) => Err("Several monitors are connected, give the monitor number".to_string()),
+        (Some(num), count) if num < 1 || num as usize > count => {
+            Err(format!("Monitor number must be between 1 and {}", count))
+        }
+        (Some(num), _) => Ok(&monitors[(num - 1) as usize]),
+    }
+}
+
+// Render an image for one monitor and set it, unless this is a dry run. Without a source, one
+// is picked from the configured sources.
+fn apply_to_monitor(
+    monitor: &MonitorInfo,
+    source: Option<&PathBuf>,
+    settings: &MonitorSettings,
+    apply: Apply,
+) -> MonitorResult {
+    let mut result = MonitorResult::new(monitor);
+    if let Err(e) = render_and_set(monitor, source, settings, apply, &mut result) {
+        result.error = Some(e);
+    }
+    result
+}
+
+fn render_and_set(
+    monitor: &MonitorInfo,
+    source: Option<&PathBuf>,
+    settings: &MonitorSettings,
+    apply: Apply,
+    result: &mut MonitorResult,
+) -> Result<(), String> {
+    let desktop_num = monitor.id as i32;
+
+    let file_path = match pick_image(source, monitor, settings) {
+        Some(path) => path,
+        None => return Err("No image given and none found in the configured sources".to_string()),
+    };
+
+    // Get absolute path
+    let absolute_path = match file_path.canonicalize() {
+        Ok(path) => path,
+        Err(e) => return Err(format!("Failed to get absolute path: {}", e)),
+    };
+    result.source = Some(absolute_path.clone());
+
+    // Get current time in seconds since UNIX epoch
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let target_stem = target_stem(&file_path, desktop_num, timestamp)?;
+    if apply == Apply::DryRun {
+        result.rendered = match encode::resolve_by_type(settings.format, &file_path) {
+            Some(format) => Some(target_stem.with_extension(format.extension())),
+            None => Some(target_stem),
+        };
+        return Ok(());
+    }
+
+    // Process the image directly from source to target
+    let target_path = adjust_image(&absolute_path, &target_stem, monitor, settings)?;
+    result.rendered = Some(target_path.clone());
+
+    // Fading is only nice to have, cut to the new image when it fails
+    if apply == Apply::Crossfade {
+        if let Err(e) = crossfade(&target_path, desktop_num) {
+            eprintln!("  Monitor {} - Crossfade skipped: {}", desktop_num, e);
+        }
+    }
+
+    // Systems with a separate dark background get one for every image, or the light one would
+    // stay in dark mode
+    if os_level::HAS_DARK_SLOT {
+        let dark_path = match &settings.dark {
+            Some(dark) => {
+                let path = render_dark(dark, &absolute_path, &target_stem, monitor, settings)?;
+                result.rendered_dark = Some(path.clone());
+                path
+            }
+            None => target_path.clone(),
+        };
+        os_level::set_dark_background(&dark_path, desktop_num)?;
+    }
+
+    // Set the picture
+    os_level::set_background(&target_path, desktop_num)?;
+    result.applied = true;
+
+    // Remember the source so the image can be rendered again when the monitor changes
+    if let Err(e) = state::record_current(monitor, &absolute_path) {
+        eprintln!("  Monitor {} - {}", desktop_num, e);
+    }
+
+    // Every monitor gets its own palette for its hooks. Themes follow the first monitor, there
+    // is only one terminal color scheme to set.
+    let mut palette_path = None;
+    if settings.palette {
+        let exported = palette::extract(&absolute_path).and_then(|palette| {
+            if desktop_num == 1 {
+                palette.export(&palette::palette_dir()?)?;
+            }
+            palette.export(&palette::monitor_palette_dir(desktop_num)?)
+        });
+        match exported {
+            Ok(written) => palette_path = written.into_iter().next(),
+            Err(e) => eprintln!("  Monitor {} - {}", desktop_num, e),
+        }
+    }
+
+    if !settings.hooks.is_empty() {
+        let vars = [
+            ("BACKGROUND_MONITOR", desktop_num.to_string()),
+            ("BACKGROUND_MONITOR_NAME", monitor.name.clone()),
+            ("BACKGROUND_MONITOR_IDENTITY", monitor.identity.clone()),
+            ("BACKGROUND_SOURCE", absolute_path.display().to_string()),
+            ("BACKGROUND_RENDERED", target_path.display().to_string()),
+            ("BACKGROUND_PALETTE", display_path(&palette_path)),
+        ];
+        for hook in &settings.hooks {
+            if let Err(e) = hooks::run(hook, &vars) {
+                result.hook_errors.push(e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Render the dark mode variant next to the light one, from an image of the dark sources or from
+// the same image darkened
+fn render_dark(
+    dark: &DarkVariant,
+    light_source: &Path,
+    target_stem: &Path,
+    monitor: &MonitorInfo,
+    settings: &MonitorSettings,
+) -> Result<PathBuf, String> {
+    let mut dark_settings = MonitorSettings {
+        dark: None,
+        ..settings.clone()
+    };
+    let source = match dark {
+        DarkVariant::Sources(sources) => {
+            dark_settings.sources = sources.clone();
+            match pick_image(None, monitor, &dark_settings) {
+                Some(path) => path,
+                None => return Err("No image found in the dark sources".to_string()),
+            }
+        }
+        DarkVariant::Darken(amount) => {
+            dark_settings.darken = *amount;
+            light_source.to_path_buf()
+        }
+    };
+
+    let mut dark_stem = target_stem.as_os_str().to_owned();
+    dark_stem.push("_dark");
+    adjust_image(&source, Path::new(&dark_stem), monitor, &dark_settings)
+}
+
+// Set a few blends of the current background and the rendered image one after the other. All
+// of them are encoded before the first is shown, so the steps are as even as setting the
+// background allows. The last one stays on screen until the caller sets the rendered image.
+fn crossfade(target_path: &Path, desktop_num: i32) -> Result<(), String> {
+    let current = os_level::get_background(desktop_num)?;
+    let from = decode::open(&current, None)?;
+    let to = decode::open(target_path, None)?;
+
+    // Both are rendered for this monitor, unless it was changed by something else
+    if from.get_width() != to.get_width() || from.get_height() != to.get_height() {
+        return Err("the current background has another size".to_string());
+    }
+
+    let frame_dir = state::data_dir()?.join("crossfade");
+    if let Err(e) = fs::create_dir_all(&frame_dir) {
+        return Err(format!("Failed to create '{}': {}", frame_dir.display(), e));
+    }
+    remove_frames(&frame_dir, desktop_num);
+
+    // Some systems don't show a file again under the same name, so every crossfade and every
+    // frame gets its own
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or(0);
+
+    let from_pixels = from.get_raw_pixels();
+    let to_pixels = to.get_raw_pixels();
+    let mut frames = Vec::new();
+    for frame in 1..=CROSSFADE_FRAMES {
+        let amount = frame as f32 / (CROSSFADE_FRAMES + 1) as f32;
+        let pixels = from_pixels
+            .iter()
+            .zip(&to_pixels)
+            .map(|(&a, &b)| (a as f32 + (b as f32 - a as f32) * amount).round() as u8)
+            .collect();
+        let blend = PhotonImage::new(pixels, to.get_width(), to.get_height());
+
+        // Each frame is only seen for a moment, JPEG is much quicker to write and read than PNG
+        let frame_path = frame_dir.join(format!("{}_{}_{}.jpg", desktop_num, started, frame));
+        encode::save(blend, &frame_path, CROSSFADE_QUALITY)?;
+        frames.push(frame_path);
+    }
+
+    for frame_path in &frames {
+        let shown = Instant::now();
+        os_level::set_background(frame_path, desktop_num)?;
+        thread::sleep(CROSSFADE_STEP.saturating_sub(shown.elapsed()));
+    }
+
+    Ok(())
+}
+
+// Frames of earlier crossfades on this monitor. None of them is on screen any more, the
+// rendered image replaced the last one.
+fn remove_frames(frame_dir: &Path, desktop_num: i32) {
+    let Ok(entries) = fs::read_dir(frame_dir) else {
+        return;
+    };
+    let prefix = format!("{}_", desktop_num);
+    for entry in entries.flatten() {
+        if entry.file_name().to_string_lossy().starts_with(&prefix) {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+fn pick_image(
+    source: Option<&PathBuf>,
+    monitor: &MonitorInfo,
+    settings: &MonitorSettings,
+) -> Option<PathBuf> {
+    let images = match source {
+        Some(path) if path.is_file() => return Some(path.clone()),
+        Some(path) => collect_for_rotation(std::slice::from_ref(path), settings),
+        None => collect_for_rotation(&settings.sources, settings),
+    };
+
+    pick_for_rotation(&images, monitor, settings)
+}
+
+// Weighted by the ratings, so favorites come up more often and banned images never
+fn pick_for_rotation(
+    images: &[PathBuf],
+    monitor: &MonitorInfo,
+    settings: &MonitorSettings,
+) -> Option<PathBuf> {
+    let ratings = Ratings::load().unwrap_or_else(|e| {
+        eprintln!("Warning: {}", e);
+        Ratings::default()
+    });
+
+    sources::pick_for_monitor(images, monitor, settings.aspect_tolerance, |path| {
+        ratings.weight(path)
+    })
+}
+
+// The images of the sources except banned ones, narrowed down to the photos of this day and
+// without near duplicates when configured
+fn collect_for_rotation(source_paths: &[PathBuf], settings: &MonitorSettings) -> Vec<PathBuf> {
+    let mut images = sources::collect_images(source_paths);
+    if let Ok(ratings) = Ratings::load() {
+        images.retain(|path| !ratings.is_banned(path));
+    }
+    if settings.on_this_day {
+        images = photos::select_on_this_day(images);
+    }
+    if settings.dedupe {
+        images = dedupe::collapse_duplicates(images);
+    }
+    images
+}
+
+// The rendered images are written to the working directory, named after the monitor and time.
+// The extension follows once the format is known.
+fn target_stem(file_path: &Path, desktop_num: i32, timestamp: u64) -> Result<PathBuf, String> {
+    // Define the target filename
+    let target_filename = format!("{}_{}", desktop_num, timestamp);
+    if let Some(stem) = file_path.file_stem() {
+        if stem.to_string_lossy() == target_filename {
+            return Err(format!(
+                "Cannot use '{}' as source - it's named like the targets for monitor {}",
+                file_path.display(),
+                desktop_num
+            ));
+        }
+    }
+
+    match env::current_dir() {
+        Ok(dir) => Ok(dir.join(target_filename)),
+        Err(e) => Err(format!("Failed to get the working directory: {}", e)),
+    }
+}
+
+// Render the source for a monitor and save the composite as the target stem with the extension
+// of the format it was written in, replacing an older file of that name. Returns its path.
+pub fn adjust_image(
+    source_path: &Path,
+    target_stem: &Path,
+    monitor: &MonitorInfo,
+    settings: &MonitorSettings,
+) -> Result<PathBuf, String> {
+    let monitor_size = (monitor.width as u32, monitor.height as u32);
+
+    // Open the image from source, drawings are rasterized for the screen
+    let img = decode::open(source_path, Some(monitor_size))?;
+    let format = encode::resolve(settings.format, source_path, &img);
+    let target_path = target_stem.with_extension(format.extension());
+
+    if target_path.exists() {
+        if let Err(e) = fs::remove_file(&target_path) {
+            return Err(format!(
+                "Failed to remove existing '{}': {}",
+                target_path.display(),
+                e
+            ));
+        }
+    }
+
+    let combined_img = compose_image(&img, source_path, monitor_size, settings);
+
+    // Save the final composite image in the format its extension names
+    encode::save(combined_img, &target_path, settings.quality)?;
+    Ok(target_path)
+}
+
+// Open the source and compose it for a screen of the given size, without saving anything
+pub fn render_image(
+    source_path: &Path,
+    screen_size: (u32, u32),
+    settings: &MonitorSettings,
+) -> Result<PhotonImage, String> {
+    // Open the image from source, drawings are rasterized for the screen
+    let img = decode::open(source_path, Some(screen_size))?;
+    Ok(compose_image(&img, source_path, screen_size, settings))
+}
+
+fn compose_image(
+    img: &PhotonImage,
+    source_path: &Path,
+    screen_size: (u32, u32),
+    settings: &MonitorSettings,
+) -> PhotonImage {
+    // Scale and combine according to the configured mode
+    let mut composite = image_proc::compose(
+        img,
+        screen_size,
+        settings.mode,
+        settings.backdrop,
+        settings.insets,
+    );
+    if settings.darken > 0.0 {
+        image_proc::darken(&mut composite, settings.darken);
+    }
+    // A broken overlay should not keep the image from being set
+    for entry in &settings.overlays {
+        if let Err(e) = overlay::draw(&mut composite, entry, source_path, settings.insets) {
+            eprintln!("Warning: {}", e);
+        }
+    }
+    composite
+}
+
+// Render what `change` would set without setting anything. Each monitor gets its own file in
+// the output folder, or with `layout` all monitors are drawn side by side into a single image.
+// Given sizes stand in for the connected monitors, so previews can be rendered where there
+// are none, like on a build server
+pub fn preview(
+    request: &ChangeRequest,
+    output: &Path,
+    layout: bool,
+    sizes: &[(usize, usize)],
+) -> Report {
+    let loaded = if sizes.is_empty() {
+        load_config_and_monitors()
+    } else {
+        Config::load()
+            .map(|config| (config, monitors_of_size(sizes)))
+            .map_err(|e| format!("Invalid configuration {}", e))
+    };
+    let (config, monitors) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => return Report::failed(e),
+    };
+    if let Err(e) = check_source(request.source.as_ref()) {
+        return Report::failed(e);
+    }
+
+    let profile = config.matching_profile(&monitors);
+    let mut report = Report {
+        profile: profile.map(|(name, _)| name.to_string()),
+        ..Default::default()
+    };
+    let profile = profile.map(|(_, profile)| profile);
+
+    if layout {
+        preview_layout(&config, &monitors, profile, request, output, &mut report);
+        return report;
+    }
+
+    let targets = match select_monitors(&monitors, &request.monitors) {
+        Ok(targets) => targets,
+        Err(e) => return Report::failed(e),
+    };
+
+    if let Err(e) = fs::create_dir_all(output) {
+        return Report::failed(format!("Failed to create '{}': {}", output.display(), e));
+    }
+
+    for monitor in targets {
+        let desktop_num = monitor.id as i32;
+        let settings = request.settings_for(&config, monitor, profile);
+        let mut result = MonitorResult::new(monitor);
+
+        match pick_image(request.source.as_ref(), monitor, &settings) {
+            Some(image) => {
+                let target = output.join(desktop_num.to_string());
+                result.source = Some(image.clone());
+                match adjust_image(&image, &target, monitor, &settings) {
+                    Ok(target) => result.rendered = Some(target),
+                    Err(e) => result.error = Some(e),
+                }
+            }
+            None => {
+                result.error =
+                    Some("No image given and none found in the configured sources".to_string())
+            }
+        }
+
+        report.monitors.push(result);
+    }
+
+    report
+}
+
+// The monitors side by side in order and bottom aligned, scaled down so the whole desktop fits
+// in one reasonably sized image
+fn preview_layout(
+    config: &Config,
+    monitors: &[MonitorInfo],
+    profile: Option<&Profile>,
+    request: &ChangeRequest,
+    output: &Path,
+    report: &mut Report,
+) {
+    let gap = LAYOUT_GAP / LAYOUT_SCALE;
+    let height = monitors
+        .iter()
+        .map(|m| (m.height as u32 / LAYOUT_SCALE).max(1))
+        .max()
+        .unwrap_or(1);
+    let width = monitors
+        .iter()
+        .map(|m| (m.width as u32 / LAYOUT_SCALE).max(1) + gap)
+        .sum::<u32>()
+        .saturating_sub(gap)
+        .max(1);
+
+    let (r, g, b) = LAYOUT_BACKGROUND;
+    let pixels = [r, g, b, 255].repeat((width * height) as usize);
+    let mut mockup = PhotonImage::new(pixels, width, height);
+
+    let mut x = 0;
+    for monitor in monitors {
+        let size = (
+            (monitor.width as u32 / LAYOUT_SCALE).max(1),
+            (monitor.height as u32 / LAYOUT_SCALE).max(1),
+        );
+        let settings = request
+            .settings_for(config, monitor, profile)
+            .scaled(1.0 / LAYOUT_SCALE as f32);
+        let mut result = MonitorResult::new(monitor);
+
+        match pick_image(request.source.as_ref(), monitor, &settings) {
+            Some(image) => {
+                result.source = Some(image.clone());
+                match render_image(&image, size, &settings) {
+                    Ok(composite) => {
+                        let y = height - size.1;
+                        photon_rs::multiple::watermark(&mut mockup, &composite, x.into(), y.into());
+                        result.rendered = Some(output.to_path_buf());
+                    }
+                    Err(e) => result.error = Some(e),
+                }
+            }
+            None => {
+                result.error =
+                    Some("No image given and none found in the configured sources".to_string())
+            }
+        }
+
+        report.monitors.push(result);
+        x += size.0 + gap;
+    }
+
+    if let Err(e) = encode::save(mockup, output, config.quality()) {
+        report.error = Some(e);
+    }
+}
+
+// Every field of every connected monitor, for scripts. Returns false on errors.
+pub fn show_monitors_json() -> bool {
+    match os_level::get_profile_info() {
+        Ok(monitors) => {
+            print_json(&monitors);
+            true
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            false
+        }
+    }
+}
+
+pub fn show_monitor_sizes() {
+    let monitor_count = match os_level::get_monitor_count() {
+        Ok(count) => count,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+
+    println!("Monitor sizes:");
+
+    for mon_number in 1..=monitor_count {
+        match os_level::get_monitor_size(mon_number) {
+            Ok((width, height)) => {
+                println!("  Monitor {}: {}x{} pixels", mon_number, width, height);
+            }
+            Err(e) => {
+                eprintln!("  Monitor {} - Error: {}", mon_number, e);
+            }
+        }
+    }
+}
+
+// What happened to one saved background, printed as text or as JSON
+#[derive(Debug, Serialize)]
+struct Restored {
+    monitor: i32,
+    name: String,
+    path: PathBuf,
+    restored: bool,
+    error: Option<String>,
+}
+
+impl Restored {
+    fn print(&self) {
+        match &self.error {
+            Some(e) => eprintln!("  Monitor {} ({}) - {}", self.monitor, self.name, e),
+            None => println!("  Monitor {} - Background restored", self.monitor),
+        }
+    }
+}
+
+pub fn restore_original(json: bool) {
+    let state = match state::State::load() {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+
+    if state.original.is_empty() {
+        eprintln!("Error: No original background has been saved yet");
+        return;
+    }
+
+    let monitors = match os_level::get_profile_info() {
+        Ok(monitors) => monitors,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+
+    if !json {
+        println!("Restoring original background...");
+    }
+
+    let mut results = Vec::new();
+    for saved in &state.original {
+        // Monitor numbers can shift when displays are rearranged, so prefer matching by identity
+        let desktop_num = match state::find_monitor(&monitors, saved) {
+            Some(monitor) => monitor.id as i32,
+            None => saved.monitor,
+        };
+
+        let mut result = Restored {
+            monitor: desktop_num,
+            name: saved.name.clone(),
+            path: saved.path.clone(),
+            restored: false,
+            error: None,
+        };
+        if desktop_num < 1 || desktop_num as usize > monitors.len() {
+            result.monitor = saved.monitor;
+            result.error = Some("Not connected, skipping".to_string());
+        } else if !saved.path.exists() {
+            result.error = Some(format!(
+                "Saved background '{}' no longer exists",
+                saved.path.display()
+            ));
+        } else {
+            match os_level::set_background(&saved.path, desktop_num) {
+                Ok(()) => result.restored = true,
+                Err(e) => result.error = Some(e),
+            }
+        }
+
+        if json {
+            results.push(result);
+        } else {
+            result.print();
+        }
+    }
+
+    if json {
+        print_json(&results);
+    }
+}
+
+// Returns false when the configuration has errors, so callers can fail the process
+// The outcome of config check, printed as text or as JSON
+#[derive(Debug, Serialize)]
+struct ConfigCheck {
+    path: PathBuf,
+    // A missing file is fine, everything has a default
+    exists: bool,
+    error: Option<String>,
+    // Schedule rules that don't start today
+    skipped_rules: Vec<String>,
+}
+
+pub fn check_config(path: Option<&str>, json: bool) -> bool {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => match config::config_path() {
+            Ok(path) => path,
+            Err(e) => {
+                eprintln!("{}", e);
+                return false;
+            }
+        },
+    };
+
+    let mut checked = ConfigCheck {
+        path: path.clone(),
+        exists: path.exists(),
+        error: None,
+        skipped_rules: Vec::new(),
+    };
+    if checked.exists {
+        match Config::check(&path) {
+            Ok(config) => {
+                let today = chrono::Local::now().date_naive();
+                checked.skipped_rules =
+                    schedule::skipped_rules(&config.schedule, config.location, today);
+            }
+            Err(e) => checked.error = Some(e),
+        }
+    }
+
+    if json {
+        print_json(&checked);
+        return checked.error.is_none();
+    }
+
+    if !checked.exists {
+        println!(
+            "No configuration file at '{}', using defaults",
+            path.display()
+        );
+        return true;
+    }
+    match &checked.error {
+        None => {
+            println!("Configuration '{}' is valid", path.display());
+            for skipped in &checked.skipped_rules {
+                eprintln!("Warning: {}", skipped);
+            }
+            true
+        }
+        Some(e) => {
+            eprintln!("Error: {}", e);
+            false
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ProfileSummary<'a> {
+    name: &'a str,
+    // Keys of the monitors it is made for
+    monitors: Vec<&'a str>,
+    // Whether it matches the connected monitors
+    active: bool,
+}
+
+pub fn list_profiles(json: bool) {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: Invalid configuration {}", e);
+            return;
+        }
+    };
+
+    let monitors = os_level::get_profile_info().unwrap_or_default();
+    let active = config.matching_profile(&monitors).map(|(name, _)| name);
+
+    if json {
+        let summaries: Vec<ProfileSummary> = config
+            .profiles
+            .iter()
+            .map(|(name, profile)| ProfileSummary {
+                name,
+                monitors: profile.monitors.keys().map(|k| k.as_str()).collect(),
+                active: active == Some(name.as_str()),
+            })
+            .collect();
+        print_json(&summaries);
+        return;
+    }
+
+    if config.profiles.is_empty() {
+        println!("No profiles configured");
+        return;
+    }
+
+    println!("Profiles:");
+    for (name, profile) in &config.profiles {
+        let marker = if active == Some(name.as_str()) {
+            " (active)"
+        } else {
+            ""
+        };
+        let keys: Vec<&str> = profile.monitors.keys().map(|k| k.as_str()).collect();
+        println!("  {}{}: {}", name, marker, keys.join(", "));
+    }
+}
+
+// Apply the profile matching the connected monitors. Unless forced, nothing happens when the
+// layout is the same as the last time this ran. Returns whether a profile was applied.
+// What profile apply or sync did, printed as text or as JSON
+#[derive(Debug, Serialize)]
+struct Synced {
+    // Whether the monitors differ from those last seen
+    layout_changed: bool,
+    // The change made for the matching profile, missing when none matches or nothing changed
+    change: Option<Report>,
+    error: Option<String>,
+}
+
+pub fn sync_profile(force: bool, json: bool) -> bool {
+    let synced = sync_layout(force);
+    if json {
+        print_json(&synced);
+    } else if let Some(e) = &synced.error {
+        eprintln!("Error: {}", e);
+    } else if let Some(report) = &synced.change {
+        report.print();
+    } else if !synced.layout_changed {
+        println!("Display layout unchanged");
+    } else {
+        println!("No profile matches the current display layout");
+    }
+    synced.change.is_some()
+}
+
+fn sync_layout(force: bool) -> Synced {
+    let mut synced = Synced {
+        layout_changed: false,
+        change: None,
+        error: None,
+    };
+    let monitors = match os_level::get_profile_info() {
+        Ok(monitors) => monitors,
+        Err(e) => {
+            synced.error = Some(e);
+            return synced;
+        }
+    };
+
+    let state = match state::State::load() {
+        Ok(state) => state,
+        Err(e) => {
+            synced.error = Some(e);
+            return synced;
+        }
+    };
+
+    synced.layout_changed = state.layout != describe_layout(&monitors);
+    if !force && !synced.layout_changed {
+        return synced;
+    }
+
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            synced.error = Some(format!("Invalid configuration {}", e));
+            return synced;
+        }
+    };
+
+    if config.matching_profile(&monitors).is_some() {
+        synced.change = Some(change(&ChangeRequest::default()));
+    }
+
+    remember_layout(&monitors);
+    synced
+}
+
+// Save the layout so the next sync can tell whether it changed. Reloads the state first,
+// applying a profile may have saved the original backgrounds in the meantime.
+fn remember_layout(monitors: &[MonitorInfo]) {
+    let result = state::State::load().and_then(|mut state| {
+        state.layout = describe_layout(monitors);
+        state.save()
+    });
+    if let Err(e) = result {
+        eprintln!("{}", e);
+    }
+}
+
+fn describe_layout(monitors: &[MonitorInfo]) -> Vec<String> {
+    let mut layout: Vec<String> = monitors
+        .iter()
+        .map(|m| format!("{} {}x{}", m.identity, m.width, m.height))
+        .collect();
+    layout.sort();
+    layout
+}
+
+// Keep running, rendering the images again for monitors that were connected or changed
+// resolution. Switches profile when the new layout matches a different one, and images when
+// the schedule does.
+pub fn watch(poll_interval: Duration) {
+    let mut watcher = match DisplayWatcher::new() {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+
+    let mut active_profile = match Config::load() {
+        Ok(config) => config
+            .matching_profile(watcher.monitors())
+            .map(|(name, _)| name.to_string()),
+        Err(_) => None,
+    };
+
+    let mut schedule = ScheduleTracker::default();
+    check_schedule(&mut schedule);
+
+    if watcher.event_driven() {
+        println!(
+            "Watching for display changes, checking every {} second(s) as well...",
+            poll_interval.as_secs()
+        );
+    } else {
+        println!(
+            "Watching for display changes every {} second(s)...",
+            poll_interval.as_secs()
+        );
+    }
+
+    loop {
+        watcher.wait(poll_interval);
+        check_schedule(&mut schedule);
+
+        let changes = match watcher.poll() {
+            Ok(changes) => changes,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+
+        if changes.is_empty() {
+            continue;
+        }
+
+        for change in &changes {
+            println!("{}", change);
+        }
+
+        // Read the config again on every change, it may have been edited in the meantime
+        let config = match Config::load() {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Error: Invalid configuration {}", e);
+                continue;
+            }
+        };
+
+        let profile = config.matching_profile(watcher.monitors());
+        let profile_name = profile.map(|(name, _)| name.to_string());
+        if profile_name != active_profile {
+            active_profile = profile_name;
+            // Applying the profile renders every monitor, the new ones included, so they only
+            // need rendering on their own when it wasn't applied
+            if profile.is_some() && sync_profile(true, false) {
+                continue;
+            }
+        }
+        remember_layout(watcher.monitors());
+
+        let current = match state::State::load() {
+            Ok(state) => state.current,
+            Err(e) => {
+                eprintln!("{}", e);
+                Vec::new()
+            }
+        };
+
+        for change in &changes {
+            let monitor = match change {
+                DisplayChange::Added(monitor) | DisplayChange::Resized(monitor) => monitor,
+                DisplayChange::Removed(_) => continue,
+            };
+
+            // Render the image it had again, or pick a new one for monitors never seen before
+            let source = current
+                .iter()
+                .find(|saved| saved.identity == monitor.identity)
+                .map(|saved| saved.path.clone())
+                .filter(|path| path.exists());
+
+            let settings = config.settings_for(monitor, profile.map(|(_, profile)| profile));
+            apply_to_monitor(monitor, source.as_ref(), &settings, Apply::Set).print(false);
+        }
+    }
+}
+
+// What one provider added to the library, printed as text or as JSON
+#[derive(Debug, Serialize)]
+struct Fetched {
+    provider: String,
+    added: Vec<LibraryEntry>,
+    error: Option<String>,
+}
+
+// Download new images into the library from the given provider, or from every configured one
+pub fn fetch(provider: Option<&str>, url: Option<&str>, json: bool) {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: Invalid configuration {}", e);
+            return;
+        }
+    };
+
+    let remotes: Vec<RemoteConfig> = match provider {
+        Some(name) => {
+            // Use the configured settings of the provider when there are any
+            let configured: Vec<RemoteConfig> = config
+                .remote
+                .iter()
+                .filter(|remote| remote.provider == name)
+                .filter(|remote| url.is_none() || remote.url.as_deref() == url)
+                .cloned()
+                .collect();
+            if configured.is_empty() {
+                vec![RemoteConfig {
+                    provider: name.to_string(),
+                    url: url.map(|url| url.to_string()),
+                    ..Default::default()
+                }]
+            } else {
+                configured
+            }
+        }
+        None => config.remote.clone(),
+    };
+
+    if remotes.is_empty() {
+        if json {
+            print_json(&Vec::<Fetched>::new());
+        } else {
+            println!("No remote sources configured");
+        }
         return;
     }
 
-    let file_path = PathBuf::from(&args[0]);
+    let mut library = match Library::load() {
+        Ok(library) => library,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let http = UreqClient::new();
+
+    let mut results = Vec::new();
+    for remote in &remotes {
+        let provider = match remote::provider_from_config(remote) {
+            Ok(provider) => provider,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                continue;
+            }
+        };
+
+        if !json {
+            println!("Fetching from {}...", provider.name());
+        }
+        let mut result = Fetched {
+            provider: provider.name().to_string(),
+            added: Vec::new(),
+            error: None,
+        };
+        match remote::fetch(provider.as_ref(), &http, &mut library) {
+            Ok(added) => result.added = added,
+            Err(e) => result.error = Some(e),
+        }
+
+        if json {
+            results.push(result);
+            continue;
+        }
+        match &result.error {
+            Some(e) => eprintln!("  {}", e),
+            None if result.added.is_empty() => println!("  Nothing new"),
+            None => {
+                for entry in &result.added {
+                    println!("  Added '{}' ({})", entry.path.display(), entry.attribution);
+                }
+            }
+        }
+    }
+
+    if let Err(e) = library.save() {
+        eprintln!("{}", e);
+    }
+    if json {
+        print_json(&results);
+    }
+}
+
+// The folder given, or else every source configured for a connected monitor
+fn source_folders(path: Option<&Path>) -> Result<Vec<PathBuf>, String> {
+    if let Some(path) = path {
+        if !path.exists() {
+            return Err(format!("'{}' does not exist", path.display()));
+        }
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let (config, monitors) = load_config_and_monitors()?;
+    let profile = config
+        .matching_profile(&monitors)
+        .map(|(_, profile)| profile);
+
+    let mut source_paths: Vec<PathBuf> = Vec::new();
+    for monitor in &monitors {
+        for source in config.settings_for(monitor, profile).sources {
+            if !source_paths.contains(&source) {
+                source_paths.push(source);
+            }
+        }
+    }
+    Ok(source_paths)
+}
+
+// Read the capture dates of all photos so later rotations don't have to
+// How many photos photos index looked at, printed as text or as JSON
+#[derive(Debug, Serialize)]
+struct Indexed {
+    images: usize,
+    // New or changed since the last time
+    read: usize,
+    // Those with a capture date
+    dated: usize,
+}
 
-    // Verify the file exists
-    if !file_path.exists() {
-        eprintln!("Error: File '{}' does not exist", file_path.display());
-        return;
+pub fn index_photos(path: Option<&Path>, json: bool) {
+    let source_paths = match source_folders(path) {
+        Ok(source_paths) => source_paths,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+
+    let mut index = match PhotoIndex::load() {
+        Ok(index) => index,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+
+    let images = sources::collect_images(&source_paths);
+    if !json {
+        println!("Indexing {} image(s)...", images.len());
     }
+    let read = index.update(&images);
 
-    if !file_path.is_file() {
-        eprintln!("Error: '{}' is not a file", file_path.display());
-        return;
+    let indexed = Indexed {
+        images: images.len(),
+        read,
+        dated: images
+            .iter()
+            .filter(|path| index.taken(path).is_some())
+            .count(),
+    };
+    if json {
+        print_json(&indexed);
+    } else {
+        println!(
+            "Read {} new or changed image(s), {} of {} have a capture date",
+            indexed.read, indexed.dated, indexed.images
+        );
     }
 
-    // Get absolute path
-    let absolute_path = match file_path.canonicalize() {
-        Ok(path) => path,
+    if let Err(e) = index.save() {
+        eprintln!("{}", e);
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DatedPhoto<'a> {
+    path: &'a Path,
+    taken: Option<chrono::NaiveDate>,
+}
+
+// Show the photos taken on this day, or else this week, in earlier years
+pub fn show_on_this_day(path: Option<&Path>, json: bool) {
+    let source_paths = match source_folders(path) {
+        Ok(source_paths) => source_paths,
         Err(e) => {
-            eprintln!("Failed to get absolute path: {}", e);
+            eprintln!("Error: {}", e);
             return;
         }
     };
 
-    println!("Selected: {}", file_path.display());
-
-    // Get the number of monitors
-    let monitor_count = match os_level::get_monitor_count() {
-        Ok(count) => count,
+    let mut index = match PhotoIndex::load() {
+        Ok(index) => index,
         Err(e) => {
             eprintln!("{}", e);
             return;
         }
     };
 
-    // Check if a specific monitor was specified
-    let target_monitors: Vec<i32> = if args.len() >= 2 {
-        // Specific monitor provided
-        match args[1].parse::<i32>() {
-            Ok(monitor_num) => {
-                if monitor_num < 1 || monitor_num > monitor_count {
-                    eprintln!(
-                        "Error: Monitor number must be between 1 and {}",
-                        monitor_count
-                    );
-                    return;
-                }
-                vec![monitor_num]
-            }
-            Err(_) => {
-                eprintln!("Error: Invalid monitor number '{}'", args[1]);
-                return;
-            }
+    let images = sources::collect_images(&source_paths);
+    if index.update(&images) > 0 {
+        if let Err(e) = index.save() {
+            eprintln!("{}", e);
+        }
+    }
+
+    let today = chrono::Local::now().date_naive();
+    let selected = photos::on_this_day(&images, &index, today);
+    if json {
+        let dated: Vec<DatedPhoto> = selected
+            .iter()
+            .map(|path| DatedPhoto {
+                path,
+                taken: index.taken(path),
+            })
+            .collect();
+        print_json(&dated);
+        return;
+    }
+    if selected.is_empty() {
+        println!("No photos taken on this day or week in earlier years");
+        return;
+    }
+
+    for path in &selected {
+        let taken = index
+            .taken(path)
+            .map(|date| date.to_string())
+            .unwrap_or_default();
+        println!("  {} {}", taken, path.display());
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DuplicateGroup<'a> {
+    // The copy rotation keeps
+    keep: Option<&'a PathBuf>,
+    images: Vec<SizedImage<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct SizedImage<'a> {
+    path: &'a PathBuf,
+    width: Option<usize>,
+    height: Option<usize>,
+}
+
+// Report the groups of near-duplicate images and which copy rotation keeps of each
+pub fn show_duplicates(path: Option<&Path>, json: bool) {
+    let source_paths = match source_folders(path) {
+        Ok(source_paths) => source_paths,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
         }
-    } else {
-        // No specific monitor, set on all monitors
-        (1..=monitor_count).collect()
     };
 
-    if target_monitors.len() == 1 {
-        println!("Setting background on monitor {}...", target_monitors[0]);
-    } else {
-        println!(
-            "Setting background on {} monitor(s)...",
-            target_monitors.len()
-        );
+    let mut index = match HashIndex::load() {
+        Ok(index) => index,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+
+    let images = sources::collect_images(&source_paths);
+    if !json {
+        println!("Hashing {} image(s)...", images.len());
+    }
+    if index.update(&images) > 0 {
+        if let Err(e) = index.save() {
+            eprintln!("{}", e);
+        }
     }
 
-    for desktop_num in target_monitors {
-        // Get current time in seconds since UNIX epoch
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+    let groups = dedupe::find_duplicates(&images, &index);
+    if json {
+        let groups: Vec<DuplicateGroup> = groups
+            .iter()
+            .map(|group| DuplicateGroup {
+                keep: dedupe::best_copy(group),
+                images: group
+                    .iter()
+                    .map(|path| {
+                        let size = decode::dimensions(path);
+                        SizedImage {
+                            path,
+                            width: size.map(|(width, _)| width),
+                            height: size.map(|(_, height)| height),
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+        print_json(&groups);
+        return;
+    }
+    if groups.is_empty() {
+        println!("No duplicates found");
+        return;
+    }
 
-        let copied_absolute_path =
-            match create_target_file(&file_path, &absolute_path, desktop_num, timestamp) {
-                Some(value) => value,
-                None => continue,
-            };
+    let duplicates: usize = groups.iter().map(|group| group.len() - 1).sum();
+    println!(
+        "{} picture(s) have {} duplicate(s):",
+        groups.len(),
+        duplicates
+    );
+    for group in &groups {
+        let best = dedupe::best_copy(group);
+        println!();
+        for path in group {
+            let size = decode::dimensions(path)
+                .map(|(width, height)| format!("{}x{}", width, height))
+                .unwrap_or_default();
+            let marker = if Some(path) == best { "keep" } else { "    " };
+            println!("  {} {:>10} {}", marker, size, path.display());
+        }
+    }
+}
+
+// Import a GNOME slideshow or macOS dynamic desktop, named after the file unless given a name
+// A dynamic wallpaper as dynamic import and list print it as JSON
+#[derive(Debug, Serialize)]
+struct DynamicSummary<'a> {
+    name: &'a str,
+    source: &'a Path,
+    images: usize,
+    follows_sun: bool,
+    // Images it names that don't exist
+    missing: Vec<&'a Path>,
+}
 
-        // Set the picture
-        os_level::set_background(&copied_absolute_path, desktop_num);
+impl<'a> DynamicSummary<'a> {
+    fn new(name: &'a str, wallpaper: &'a DynamicWallpaper) -> DynamicSummary<'a> {
+        DynamicSummary {
+            name,
+            source: &wallpaper.source,
+            images: wallpaper.frames.len(),
+            follows_sun: wallpaper.follows_sun(),
+            missing: wallpaper
+                .frames
+                .iter()
+                .map(|frame| frame.image.as_path())
+                .filter(|image| !image.exists())
+                .collect(),
+        }
     }
 }
 
-fn create_target_file(
-    file_path: &PathBuf,
-    absolute_path: &PathBuf,
-    desktop_num: i32,
-    timestamp: u64,
-) -> Option<PathBuf> {
-    // Define the target filename
-    let target_filename = format!("{}_{}.png", desktop_num, timestamp);
-    if let Some(filename) = file_path.file_name() {
-        if filename.to_string_lossy() == target_filename {
-            eprintln!(
-                "Error: Cannot use '{}' as source - it's the target filename for monitor {}",
-                target_filename, desktop_num
-            );
-            return None;
+pub fn import_dynamic(path: &Path, name: Option<&str>, json: bool) {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => match path.file_stem() {
+            Some(stem) => stem.to_string_lossy().to_string(),
+            None => {
+                eprintln!("Error: Give a name for '{}'", path.display());
+                return;
+            }
+        },
+    };
+
+    let wallpaper = match dynamic::import(path, &name) {
+        Ok(wallpaper) => wallpaper,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
         }
+    };
+
+    let summary = DynamicSummary::new(&name, &wallpaper);
+    if json {
+        print_json(&summary);
+        return;
     }
 
-    // Using the new name, set the path.
-    let target_path = PathBuf::from(&target_filename);
-    if target_path.exists() {
-        match fs::remove_file(&target_path) {
-            Ok(_) => println!("  Removed existing '{}'", target_filename),
-            Err(e) => {
-                eprintln!(
-                    "  Monitor {} - Failed to remove existing '{}': {}",
-                    desktop_num, target_filename, e
-                );
-                return None;
+    let kind = if summary.follows_sun {
+        "following the sun"
+    } else {
+        "following the clock"
+    };
+    println!(
+        "Imported '{}' with {} image(s) {}",
+        name, summary.images, kind
+    );
+    for image in &summary.missing {
+        eprintln!("Warning: '{}' does not exist", image.display());
+    }
+    println!(
+        "Use it by setting dynamic = \"{}\" in the configuration",
+        name
+    );
+}
+
+pub fn list_dynamic(json: bool) {
+    let names = match DynamicWallpaper::list() {
+        Ok(names) => names,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+
+    let loaded: Vec<(&String, Result<DynamicWallpaper, String>)> = names
+        .iter()
+        .map(|name| (name, DynamicWallpaper::load(name)))
+        .collect();
+
+    if json {
+        let mut summaries = Vec::new();
+        for (name, wallpaper) in &loaded {
+            match wallpaper {
+                Ok(wallpaper) => summaries.push(DynamicSummary::new(name, wallpaper)),
+                Err(e) => eprintln!("{}: {}", name, e),
             }
         }
+        print_json(&summaries);
+        return;
+    }
+    if names.is_empty() {
+        println!("No dynamic wallpapers imported");
+        return;
+    }
+
+    println!("Dynamic wallpapers:");
+    for (name, wallpaper) in &loaded {
+        match wallpaper {
+            Ok(wallpaper) => println!(
+                "  {}: {} image(s) from '{}'",
+                name,
+                wallpaper.frames.len(),
+                wallpaper.source.display()
+            ),
+            Err(e) => eprintln!("  {}: {}", name, e),
+        }
     }
+}
 
-    // Process the image directly from source to target
-    adjust_image(absolute_path, &target_path, desktop_num);
+// When each image of a dynamic wallpaper shows today
+// When an image of a dynamic wallpaper starts today, printed as text or as JSON
+#[derive(Debug, Serialize)]
+struct TimelineEntry<'a> {
+    start: chrono::NaiveTime,
+    image: &'a Path,
+}
 
-    // Get the absolute path of the created file
-    let copied_absolute_path = match target_path.canonicalize() {
-        Ok(path) => path,
+pub fn show_dynamic(name: &str, json: bool) {
+    let wallpaper = match DynamicWallpaper::load(name) {
+        Ok(wallpaper) => wallpaper,
         Err(e) => {
-            eprintln!(
-                "  Monitor {} - Failed to get absolute path of '{}': {}",
-                desktop_num, target_filename, e
-            );
-            return None;
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: Invalid configuration {}", e);
+            return;
         }
     };
-    Some(copied_absolute_path)
+
+    if wallpaper.follows_sun() && config.location.is_none() {
+        eprintln!(
+            "Error: '{}' follows the sun, set a [location] in the configuration",
+            name
+        );
+        return;
+    }
+
+    let today = chrono::Local::now().date_naive();
+    let timeline: Vec<TimelineEntry> = wallpaper
+        .timeline(today, config.location)
+        .into_iter()
+        .map(|(start, frame)| TimelineEntry {
+            start,
+            image: &frame.image,
+        })
+        .collect();
+    if json {
+        print_json(&timeline);
+        return;
+    }
+
+    for entry in &timeline {
+        println!(
+            "  {} {}",
+            entry.start.format("%H:%M"),
+            entry.image.display()
+        );
+    }
+}
+
+// Render every image of the rotation for a monitor into the output folder and write a GNOME
+// slideshow cycling through them, so the desktop rotates without this tool running. Images
+// change at the configured interval and blend into each other for `transition`.
+// What export gnome-xml wrote, printed as text or as JSON
+#[derive(Debug, Serialize)]
+struct Exported<'a> {
+    slideshow: PathBuf,
+    images: Vec<PathBuf>,
+    failed: Vec<FailedImage<'a>>,
 }
 
-pub fn adjust_image(source_path: &PathBuf, target_path: &PathBuf, desktop_num: i32) {
-    // Get the monitor size
-    let (monitor_width, monitor_height) = match os_level::get_monitor_size(desktop_num) {
-        Ok(size) => size,
+// A source that couldn't be rendered
+#[derive(Debug, Serialize)]
+struct FailedImage<'a> {
+    source: &'a Path,
+    error: String,
+}
+
+pub fn export_gnome_xml(
+    output: &Path,
+    monitor_num: Option<i32>,
+    source: Option<&Path>,
+    transition: Duration,
+    json: bool,
+) {
+    let (config, monitors) = match load_config_and_monitors() {
+        Ok(loaded) => loaded,
         Err(e) => {
-            eprintln!(
-                "  Monitor {} - Failed to get monitor size: {}",
-                desktop_num, e
-            );
+            eprintln!("Error: {}", e);
             return;
         }
     };
+    let monitor = match select_one_monitor(&monitors, monitor_num) {
+        Ok(monitor) => monitor,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    let desktop_num = monitor.id as i32;
 
-    println!(
-        "  Monitor {} - Processing image from '{}'",
-        desktop_num,
-        source_path.display()
-    );
+    let profile = config
+        .matching_profile(&monitors)
+        .map(|(_, profile)| profile);
+    let settings = config.settings_for(monitor, profile);
+    let source_paths = match source {
+        Some(path) if !path.exists() => {
+            eprintln!("Error: '{}' does not exist", path.display());
+            return;
+        }
+        Some(path) => vec![path.to_path_buf()],
+        None => settings.sources.clone(),
+    };
+
+    let images = collect_for_rotation(&source_paths, &settings);
+    if images.is_empty() {
+        eprintln!("Error: No images found to export");
+        return;
+    }
 
-    // Open the image from source
-    let img = match photon_rs::native::open_image(source_path.to_str().unwrap()) {
-        Ok(image) => image,
+    if let Err(e) = fs::create_dir_all(output) {
+        eprintln!("Error: Failed to create '{}': {}", output.display(), e);
+        return;
+    }
+    // GNOME needs absolute paths in the slideshow
+    let output = match output.canonicalize() {
+        Ok(output) => output,
         Err(e) => {
-            eprintln!("  Monitor {} - Failed to open image: {}", desktop_num, e);
+            eprintln!("Error: Failed to get absolute path: {}", e);
             return;
         }
     };
 
-    // Scale to fit
-    let fit_img = image_proc::fit_to_size(&img, (monitor_width, monitor_height));
+    if !json {
+        println!(
+            "Rendering {} image(s) for monitor {}...",
+            images.len(),
+            desktop_num
+        );
+    }
+    let mut rendered = Vec::new();
+    let mut failed = Vec::new();
+    for (i, image) in images.iter().enumerate() {
+        let target = output.join(format!("{:04}", i + 1));
+        match adjust_image(image, &target, monitor, &settings) {
+            Ok(target) => rendered.push(target),
+            Err(e) => {
+                if !json {
+                    eprintln!("  '{}' - {}", image.display(), e);
+                }
+                failed.push(FailedImage {
+                    source: image,
+                    error: e,
+                });
+            }
+        }
+    }
+
+    if rendered.is_empty() {
+        eprintln!("Error: None of the images could be rendered");
+        return;
+    }
+
+    // Also counts the transition towards the interval, so the rotation keeps its pace
+    let interval = config.interval().as_secs();
+    let transition = transition.as_secs().min(interval.saturating_sub(1));
+    let start = chrono::Local::now().naive_local();
+    let xml = dynamic::gnome_xml(&rendered, interval - transition, transition, start);
+
+    let xml_path = output.join("slideshow.xml");
+    if let Err(e) = fs::write(&xml_path, xml) {
+        eprintln!("Error: Failed to write '{}': {}", xml_path.display(), e);
+        return;
+    }
+
+    if json {
+        print_json(&Exported {
+            slideshow: xml_path,
+            images: rendered,
+            failed,
+        });
+    } else {
+        println!(
+            "Wrote '{}' with {} image(s)",
+            xml_path.display(),
+            rendered.len()
+        );
+    }
+}
 
-    // Scale to fill
-    let fill_img = image_proc::fill_to_size(&img, (monitor_width, monitor_height));
+// Print the colors of an image, or of the one shown on a monitor, and write them out as themes
+pub fn export_palette(
+    file: Option<&Path>,
+    monitor_num: Option<i32>,
+    output: Option<&Path>,
+    json: bool,
+) {
+    let source = match file {
+        Some(file) => file.to_path_buf(),
+        None => match shown_image(monitor_num) {
+            Ok(path) => path,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return;
+            }
+        },
+    };
 
-    // Combine fit and fill images
-    let combined_img =
-        image_proc::combine_fit_and_fill(&fit_img, &fill_img, (monitor_width, monitor_height));
+    let palette = match palette::extract(&source) {
+        Ok(palette) => palette,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
 
-    // Save the final composite image
-    match photon_rs::native::save_image(combined_img, target_path.to_str().unwrap()) {
-        Ok(_) => println!(
-            "  Monitor {} - Successfully processed and saved image ({}x{})",
-            desktop_num, monitor_width, monitor_height
-        ),
+    let dir = match output {
+        Some(output) => output.to_path_buf(),
+        None => match palette::palette_dir() {
+            Ok(dir) => dir,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return;
+            }
+        },
+    };
+    let written = match palette.export(&dir) {
+        Ok(written) => written,
         Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+
+    if json {
+        print_json(&palette);
+        return;
+    }
+
+    println!("Colors of '{}':", source.display());
+    for color in &palette.colors {
+        println!("  {} {:>5.1}%", color.hex, color.share * 100.0);
+    }
+    for path in &written {
+        println!("Wrote '{}'", path.display());
+    }
+}
+
+// The source of the image on a monitor, which may only be left out when there is just one
+fn shown_image(monitor_num: Option<i32>) -> Result<PathBuf, String> {
+    let monitors = os_level::get_profile_info()?;
+    let monitor = select_one_monitor(&monitors, monitor_num)?;
+
+    let state = state::State::load()?;
+    match state
+        .current
+        .into_iter()
+        .find(|saved| saved.identity == monitor.identity)
+    {
+        Some(saved) => Ok(saved.path),
+        None => Err(format!(
+            "Monitor {} is not showing an image set by this tool",
+            monitor.id
+        )),
+    }
+}
+
+// What to change about the rating of the image shown on a monitor
+#[derive(Debug, Clone, Copy)]
+pub enum RatingChange {
+    Favorite(bool),
+    Ban(bool),
+    // No stars clears the rating
+    Stars(Option<u8>),
+}
+
+#[derive(Debug, Serialize)]
+struct RatingResult {
+    monitor: i32,
+    image: PathBuf,
+    rating: ImageRating,
+    // What a banned image was replaced with
+    replaced: Option<Report>,
+}
+
+// Rate the image currently shown on a monitor. With several monitors connected the monitor has
+// to be given. A banned image is replaced right away.
+pub fn rate_current(monitor_num: Option<i32>, change: RatingChange, json: bool) {
+    if let RatingChange::Stars(Some(stars)) = change {
+        if !(1..=ratings::MAX_STARS).contains(&stars) {
             eprintln!(
-                "  Monitor {} - Failed to save processed image: {}",
-                desktop_num, e
+                "Error: A rating must be between 1 and {} stars, or 0 to clear it",
+                ratings::MAX_STARS
             );
+            return;
         }
     }
-}
 
-pub fn show_monitor_sizes() {
-    let monitor_count = match os_level::get_monitor_count() {
-        Ok(count) => count,
+    let monitors = match os_level::get_profile_info() {
+        Ok(monitors) => monitors,
         Err(e) => {
             eprintln!("{}", e);
             return;
         }
     };
+    let monitor = match select_one_monitor(&monitors, monitor_num) {
+        Ok(monitor) => monitor,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    let desktop_num = monitor.id as i32;
 
-    println!("Monitor sizes:");
+    let current = match state::State::load() {
+        Ok(state) => state
+            .current
+            .into_iter()
+            .find(|saved| saved.identity == monitor.identity),
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let path = match current {
+        Some(saved) => saved.path,
+        None => {
+            eprintln!(
+                "Error: Monitor {} is not showing an image set by this tool",
+                desktop_num
+            );
+            return;
+        }
+    };
 
-    for mon_number in 1..=monitor_count {
-        match os_level::get_monitor_size(mon_number) {
-            Ok((width, height)) => {
-                println!("  Monitor {}: {}x{} pixels", mon_number, width, height);
-            }
-            Err(e) => {
-                eprintln!("  Monitor {} - Error: {}", mon_number, e);
-            }
+    let mut ratings = match Ratings::load() {
+        Ok(ratings) => ratings,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
         }
+    };
+
+    ratings.update(&path, |rating| match change {
+        RatingChange::Favorite(favorite) => rating.favorite = favorite,
+        RatingChange::Ban(banned) => rating.banned = banned,
+        RatingChange::Stars(stars) => rating.stars = stars,
+    });
+    if let Err(e) = ratings.save() {
+        eprintln!("{}", e);
+        return;
+    }
+
+    let replaced = match change {
+        RatingChange::Ban(true) => Some(rotate(Some(desktop_num), Apply::Set)),
+        _ => None,
+    };
+    if json {
+        print_json(&RatingResult {
+            monitor: desktop_num,
+            rating: ratings.get(&path).cloned().unwrap_or_default(),
+            image: path,
+            replaced,
+        });
+        return;
+    }
+
+    let message = match change {
+        RatingChange::Favorite(true) => "Marked as favorite".to_string(),
+        RatingChange::Favorite(false) => "No longer a favorite".to_string(),
+        RatingChange::Ban(true) => "Banned".to_string(),
+        RatingChange::Ban(false) => "No longer banned".to_string(),
+        RatingChange::Stars(Some(stars)) => format!("Rated {} star(s)", stars),
+        RatingChange::Stars(None) => "Rating cleared".to_string(),
+    };
+    println!(
+        "  Monitor {} - {} '{}'",
+        desktop_num,
+        message,
+        path.display()
+    );
+
+    if let Some(replaced) = replaced {
+        replaced.print();
     }
 }
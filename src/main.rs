@@ -1,22 +1,485 @@
 // Prevent console window in addition to Slint window in Windows release builds when, e.g., starting the app via file manager. Ignored on other platforms.
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::error::Error;
+use clap::{Args, Parser, Subcommand};
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
 
-slint::include_modules!();
+use background_manager::config;
+use background_manager::gui;
+use background_manager::image_proc::{Backdrop, Mode};
+use background_manager::logic::{self, ChangeRequest, RatingChange, Report};
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+use background_manager::tray;
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let ui = AppWindow::new()?;
+#[derive(Parser)]
+#[command(name = "background_manager")]
+#[command(about = "Background Manager", long_about = None)]
+struct Cli {
+    /// Opens the manager window when no command is given
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Open the manager window
+    Gui,
+    /// Show the tray icon and rotate the backgrounds at the configured interval
+    Tray,
+    /// Show the pixel size of each monitor
+    Displays {
+        /// Print every monitor detail as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Set the specified image as background
+    Change {
+        /// Path to the image file or a folder to pick from (if not specified, uses the configured sources)
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Optional monitor numbers (if not specified, applies to all monitors)
+        monitors: Vec<i32>,
+        #[command(flatten)]
+        render: RenderArgs,
+        /// Only show which image would be rendered for each monitor and where
+        #[arg(long)]
+        dry_run: bool,
+        /// Print the result as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Render the backgrounds into files without setting them
+    Preview {
+        /// Folder to write one image per monitor into, or the image file with --layout
+        output: PathBuf,
+        /// Path to the image file or a folder to pick from (if not specified, uses the configured sources)
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Monitor number, can be repeated (if not specified, renders all monitors)
+        #[arg(long = "monitor", conflicts_with = "layout")]
+        monitors: Vec<i32>,
+        #[command(flatten)]
+        render: RenderArgs,
+        /// Draw all monitors side by side into a single image
+        #[arg(long)]
+        layout: bool,
+        /// Render for a monitor of this size instead of the connected ones, like 2560x1440. Can be repeated.
+        #[arg(long = "size", value_parser = parse_size)]
+        sizes: Vec<(usize, usize)>,
+        /// Print the result as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Download new images into the library from online sources
+    Fetch {
+        /// bing, apod, json-feed or rss (if not specified, uses the configured sources)
+        provider: Option<String>,
+        /// Feed address for json-feed and rss
+        #[arg(long)]
+        url: Option<String>,
+        /// Print what was added as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Work with the image library
+    Library {
+        #[command(subcommand)]
+        command: LibraryCommands,
+    },
+    /// Work with photo folders indexed by capture date
+    Photos {
+        #[command(subcommand)]
+        command: PhotosCommands,
+    },
+    /// Work with wallpapers that change with the time of day
+    Dynamic {
+        #[command(subcommand)]
+        command: DynamicCommands,
+    },
+    /// Write the rotation in formats desktops can play by themselves
+    Export {
+        #[command(subcommand)]
+        command: ExportCommands,
+    },
+    /// Extract the colors of a wallpaper and write them as terminal and CSS themes
+    Palette {
+        /// Image to use (defaults to the one shown on the monitor)
+        file: Option<PathBuf>,
+        /// Monitor number (needed without a file when several monitors are connected)
+        #[arg(long)]
+        monitor: Option<i32>,
+        /// Folder to write the theme files into (defaults to the user data directory)
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Print the palette as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Mark the image shown on a monitor as favorite, so it comes up more often
+    Favorite {
+        /// Monitor number (needed when several monitors are connected)
+        monitor: Option<i32>,
+        /// Unmark it instead
+        #[arg(long)]
+        remove: bool,
+        /// Print the new rating as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Never show the image on a monitor again and replace it right away
+    Ban {
+        /// Monitor number (needed when several monitors are connected)
+        monitor: Option<i32>,
+        /// Lift the ban instead
+        #[arg(long)]
+        remove: bool,
+        /// Print the new rating and the replacement as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Rate the image shown on a monitor, higher rated images come up more often
+    Rate {
+        /// From 1 to 5 stars, 0 clears the rating
+        stars: u8,
+        /// Monitor number (needed when several monitors are connected)
+        monitor: Option<i32>,
+        /// Print the new rating as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Restore the backgrounds that were set before the first change
+    RestoreOriginal {
+        /// Print the result as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Work with the configuration file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Work with display layout profiles
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommands,
+    },
+    /// Keep running and re-render the backgrounds when monitors are connected or resized
+    Watch {
+        /// Seconds between display checks, on top of the change notifications the system sends
+        #[arg(long, default_value_t = 5)]
+        poll: u64,
+    },
+}
+
+// Overrides for the configured rendering settings
+#[derive(Args)]
+struct RenderArgs {
+    /// How the image is scaled: fit, fill or stretch
+    #[arg(long)]
+    mode: Option<Mode>,
+    /// What is shown around a fitted image: blur or a color like '#1e1e2e'
+    #[arg(long)]
+    backdrop: Option<Backdrop>,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Validate the configuration file
+    Check {
+        /// Path to the file (defaults to the one in the user config directory)
+        path: Option<String>,
+        /// Print the result as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum LibraryCommands {
+    /// Report near-duplicate images and which copy is kept in rotation
+    Dedupe {
+        /// Folder to check (defaults to the configured sources)
+        path: Option<PathBuf>,
+        /// Print the duplicate groups as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum PhotosCommands {
+    /// Read the capture dates of all photos ahead of time
+    Index {
+        /// Folder to index (defaults to the configured sources)
+        path: Option<PathBuf>,
+        /// Print the counts as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the photos taken on this day, or else within three days of it, in earlier years
+    OnThisDay {
+        /// Folder to search (defaults to the configured sources)
+        path: Option<PathBuf>,
+        /// Print the photos and their dates as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum DynamicCommands {
+    /// Import a GNOME slideshow (.xml) or a macOS dynamic desktop (.heic)
+    Import {
+        file: PathBuf,
+        /// Name to use it by in the configuration (defaults to the file name)
+        #[arg(long)]
+        name: Option<String>,
+        /// Print the imported wallpaper as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the imported dynamic wallpapers
+    List {
+        /// Print the wallpapers as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show when each image of a dynamic wallpaper is shown today
+    Show {
+        name: String,
+        /// Print the start times as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ExportCommands {
+    /// Render the images for a monitor and write a GNOME background slideshow cycling through them
+    GnomeXml {
+        /// Folder to write the images and slideshow.xml into
+        output: PathBuf,
+        /// Monitor number to render for (needed when several monitors are connected)
+        #[arg(long)]
+        monitor: Option<i32>,
+        /// Path to an image file or a folder to export (if not specified, uses the configured sources)
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Seconds each image takes to blend into the next
+        #[arg(long, default_value_t = 5)]
+        transition: u64,
+        /// Print the written files as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
 
-    ui.on_request_increase_value({
-        let ui_handle = ui.as_weak();
-        move || {
-            let ui = ui_handle.unwrap();
-            ui.set_counter(ui.get_counter() + 1);
+#[derive(Subcommand)]
+enum ProfileCommands {
+    /// List the configured profiles and which one matches the connected monitors
+    List {
+        /// Print the profiles as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Apply the profile matching the connected monitors
+    Apply {
+        /// Print the result as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Apply the matching profile only if the display layout changed since the last sync
+    Sync {
+        /// Print the result as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+fn main() {
+    // Started with arguments it is used from a terminal, which also has to show help and errors
+    if env::args_os().len() > 1 {
+        attach_console();
+    }
+    let cli = Cli::parse();
+
+    match cli.command.unwrap_or(Commands::Gui) {
+        Commands::Gui => {
+            if let Err(e) = gui::run() {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        #[cfg(any(target_os = "windows", target_os = "macos"))]
+        Commands::Tray => {
+            if let Err(e) = tray::run() {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+        Commands::Tray => {
+            eprintln!("Error: The tray icon is only available on Windows and macOS");
+            process::exit(1);
+        }
+        Commands::Displays { json: false } => logic::show_monitor_sizes(),
+        Commands::Displays { json: true } => {
+            if !logic::show_monitors_json() {
+                process::exit(1);
+            }
+        }
+        Commands::Change {
+            file,
+            monitors,
+            render,
+            dry_run,
+            json,
+        } => {
+            let request = ChangeRequest {
+                source: file,
+                monitors,
+                mode: render.mode,
+                backdrop: render.backdrop,
+                dry_run,
+            };
+            finish(&logic::change(&request), json);
+        }
+        Commands::Preview {
+            output,
+            file,
+            monitors,
+            render,
+            layout,
+            sizes,
+            json,
+        } => {
+            let request = ChangeRequest {
+                source: file,
+                monitors,
+                mode: render.mode,
+                backdrop: render.backdrop,
+                dry_run: false,
+            };
+            finish(&logic::preview(&request, &output, layout, &sizes), json);
+        }
+        Commands::Fetch {
+            provider,
+            url,
+            json,
+        } => logic::fetch(provider.as_deref(), url.as_deref(), json),
+        Commands::Library {
+            command: LibraryCommands::Dedupe { path, json },
+        } => logic::show_duplicates(path.as_deref(), json),
+        Commands::Photos { command } => match command {
+            PhotosCommands::Index { path, json } => logic::index_photos(path.as_deref(), json),
+            PhotosCommands::OnThisDay { path, json } => {
+                logic::show_on_this_day(path.as_deref(), json)
+            }
+        },
+        Commands::Dynamic { command } => match command {
+            DynamicCommands::Import { file, name, json } => {
+                logic::import_dynamic(&file, name.as_deref(), json)
+            }
+            DynamicCommands::List { json } => logic::list_dynamic(json),
+            DynamicCommands::Show { name, json } => logic::show_dynamic(&name, json),
+        },
+        Commands::Export {
+            command:
+                ExportCommands::GnomeXml {
+                    output,
+                    monitor,
+                    file,
+                    transition,
+                    json,
+                },
+        } => logic::export_gnome_xml(
+            &output,
+            monitor,
+            file.as_deref(),
+            Duration::from_secs(transition),
+            json,
+        ),
+        Commands::Palette {
+            file,
+            monitor,
+            output,
+            json,
+        } => logic::export_palette(file.as_deref(), monitor, output.as_deref(), json),
+        Commands::Favorite {
+            monitor,
+            remove,
+            json,
+        } => logic::rate_current(monitor, RatingChange::Favorite(!remove), json),
+        Commands::Ban {
+            monitor,
+            remove,
+            json,
+        } => logic::rate_current(monitor, RatingChange::Ban(!remove), json),
+        Commands::Rate {
+            stars,
+            monitor,
+            json,
+        } => {
+            let stars = if stars == 0 { None } else { Some(stars) };
+            logic::rate_current(monitor, RatingChange::Stars(stars), json)
         }
-    });
+        Commands::RestoreOriginal { json } => logic::restore_original(json),
+        Commands::Config {
+            command: ConfigCommands::Check { path, json },
+        } => {
+            if !logic::check_config(path.as_deref(), json) {
+                process::exit(1);
+            }
+        }
+        Commands::Profile { command } => match command {
+            ProfileCommands::List { json } => logic::list_profiles(json),
+            ProfileCommands::Apply { json } => {
+                logic::sync_profile(true, json);
+            }
+            ProfileCommands::Sync { json } => {
+                logic::sync_profile(false, json);
+            }
+        },
+        Commands::Watch { poll } => logic::watch(Duration::from_secs(poll)),
+    }
+}
+
+// Release builds on Windows have no console of their own, so output goes to the one of the
+// terminal they were started from. Nothing happens when there is none.
+#[cfg(windows)]
+fn attach_console() {
+    use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    unsafe {
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_console() {}
+
+// A monitor size for previews, like "2560x1440"
+fn parse_size(size: &str) -> Result<(usize, usize), String> {
+    match config::parse_resolution(size)? {
+        (0, _) | (_, 0) => Err(format!(
+            "invalid size '{}', width and height must be above 0",
+            size
+        )),
+        size => Ok(size),
+    }
+}
 
-    ui.run()?;
+// Print the report and fail the process if anything went wrong
+fn finish(report: &Report, json: bool) {
+    if json {
+        report.print_json();
+    } else {
+        report.print();
+    }
 
-    Ok(())
+    if !report.succeeded() {
+        process::exit(1);
+    }
 }
@@ -0,0 +1,61 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+// Follow the kernel's DRM uevents, sent when a monitor is plugged in or out, and X11's RandR
+// screen change events, sent when one is switched on or off or changes resolution. Either is
+// enough, most systems have udevadm but only X11 sessions have RandR. The listeners stop with
+// their commands, and once both did the caller sees the channel disconnect.
+pub fn watch(notify: Sender<()>) -> Result<(), String> {
+    // Kernel events look like "KERNEL[4711.123456] change   /devices/.../drm/card1 (drm)"
+    let uevents = follow(
+        Command::new("udevadm").args(["monitor", "--kernel", "--subsystem-match=drm"]),
+        |line| line.starts_with("KERNEL["),
+        notify.clone(),
+    );
+
+    // Events look like "RRScreenChangeNotify event, serial 18, synthetic NO, window 0x1e2"
+    let randr = follow(
+        Command::new("xev").args(["-root", "-event", "randr"]),
+        |line| line.starts_with("RRScreenChangeNotify"),
+        notify,
+    );
+
+    match (uevents, randr) {
+        (Err(e), Err(_)) => Err(format!("Display changes can't be followed here: {}", e)),
+        _ => Ok(()),
+    }
+}
+
+// Run the command in the background and notify for every line of its output that is an event
+fn follow(
+    command: &mut Command,
+    is_event: fn(&str) -> bool,
+    notify: Sender<()>,
+) -> Result<(), String> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let mut child = match command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => return Err(format!("Failed to execute {}: {}", program, e)),
+    };
+    let Some(stdout) = child.stdout.take() else {
+        return Err(format!("Failed to read the output of {}", program));
+    };
+
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if is_event(&line) && notify.send(()).is_err() {
+                break;
+            }
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+    });
+    Ok(())
+}
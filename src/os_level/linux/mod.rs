@@ -0,0 +1,111 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use url::Url;
+
+use crate::os_level::{Insets, MonitorInfo};
+
+mod displays;
+mod portal;
+
+pub use displays::watch as watch_displays;
+pub use portal::get_appearance;
+
+const BACKGROUND_SCHEMA: &str = "org.gnome.desktop.background";
+
+// Ask xrandr for the outputs that are connected and switched on. Their lines look like
+// "DP-1 connected primary 2560x1440+0+0 (normal left inverted right) 597mm x 336mm".
+pub fn get_profile_info() -> Result<Vec<MonitorInfo>, String> {
+    let output = match Command::new("xrandr").arg("--query").output() {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Failed to execute xrandr: {}", e)),
+    };
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    let re = regex::Regex::new(r"^(\d+)x(\d+)[+-]\d+[+-]\d+$").unwrap();
+    let mut monitors = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let mut words = line.split_whitespace();
+        let (Some(name), Some("connected")) = (words.next(), words.next()) else {
+            continue;
+        };
+
+        // Outputs that are connected but switched off have no geometry
+        let size = words.find_map(|word| {
+            let caps = re.captures(word)?;
+            Some((caps[1].parse().ok()?, caps[2].parse().ok()?))
+        });
+        if let Some((width, height)) = size {
+            monitors.push(MonitorInfo {
+                name: name.to_string(),
+                identity: name.to_string(),
+                id: monitors.len() + 1,
+                width,
+                height,
+                // Panels belong to the desktop shell, xrandr doesn't know them
+                insets: Insets::default(),
+            });
+        }
+    }
+
+    if monitors.is_empty() {
+        Err("No monitors found".to_string())
+    } else {
+        Ok(monitors)
+    }
+}
+
+// GNOME shows one picture on every monitor, so the last monitor set wins. This is the one for
+// light mode, set_dark_background sets the one for dark mode.
+pub fn set_background(absolute_path: &Path, _desktop_num: i32) -> Result<(), String> {
+    set_picture("picture-uri", absolute_path)
+}
+
+// GNOME switches to this one by itself while the dark style is on
+pub fn set_dark_background(absolute_path: &Path, _desktop_num: i32) -> Result<(), String> {
+    set_picture("picture-uri-dark", absolute_path)
+}
+
+fn set_picture(key: &str, absolute_path: &Path) -> Result<(), String> {
+    let uri = match Url::from_file_path(absolute_path) {
+        Ok(uri) => uri,
+        Err(()) => return Err(format!("'{}' is not absolute", absolute_path.display())),
+    };
+
+    let output = match Command::new("gsettings")
+        .args(["set", BACKGROUND_SCHEMA, key, uri.as_str()])
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Failed to execute gsettings: {}", e)),
+    };
+    if !output.status.success() {
+        return Err(format!(
+            "Error: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+pub fn get_background(_desktop_num: i32) -> Result<PathBuf, String> {
+    let output = match Command::new("gsettings")
+        .args(["get", BACKGROUND_SCHEMA, "picture-uri"])
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Failed to execute gsettings: {}", e)),
+    };
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    // Printed as a quoted GVariant string like 'file:///home/me/a.png'
+    let reply = String::from_utf8_lossy(&output.stdout);
+    let uri = reply.trim().trim_matches('\'');
+    Url::parse(uri)
+        .ok()
+        .and_then(|url| url.to_file_path().ok())
+        .ok_or_else(|| format!("The background '{}' is not a local file", uri))
+}
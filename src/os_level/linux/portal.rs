@@ -0,0 +1,120 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::os_level::Appearance;
+
+const PORTAL_DEST: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+
+// The scheme last read from the portal, kept up to date by a listener for its SettingChanged
+// signal. Empty until first asked for and again after the listener stopped.
+static CURRENT: Mutex<Option<Appearance>> = Mutex::new(None);
+
+// Ask the XDG desktop portal for the color scheme the user prefers. Only the first call talks
+// to the portal, later ones get what the listener last heard.
+pub fn get_appearance() -> Result<Appearance, String> {
+    if let Some(appearance) = *CURRENT.lock().unwrap() {
+        return Ok(appearance);
+    }
+
+    let appearance = read_appearance()?;
+    *CURRENT.lock().unwrap() = Some(appearance);
+    listen();
+    Ok(appearance)
+}
+
+// It answers with a variant like "(<<uint32 1>>,)", where 1 means dark, 2 light and 0 no
+// preference
+fn read_appearance() -> Result<Appearance, String> {
+    let output = match Command::new("gdbus")
+        .args([
+            "call",
+            "--session",
+            "--dest",
+            PORTAL_DEST,
+            "--object-path",
+            PORTAL_PATH,
+            "--method",
+            "org.freedesktop.portal.Settings.Read",
+            "org.freedesktop.appearance",
+            "color-scheme",
+        ])
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Failed to execute gdbus: {}", e)),
+    };
+
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to read the color scheme from the desktop portal: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let reply = String::from_utf8_lossy(&output.stdout);
+    match last_number(&reply) {
+        Some(scheme) => Ok(from_scheme(scheme)),
+        None => Err(format!(
+            "Unexpected reply from the desktop portal: {}",
+            reply
+        )),
+    }
+}
+
+// Follow the portal's signals in the background. Changes show up as lines like
+// "/org/freedesktop/portal/desktop: org.freedesktop.portal.Settings.SettingChanged
+// ('org.freedesktop.appearance', 'color-scheme', <uint32 1>)".
+fn listen() {
+    let child = Command::new("gdbus")
+        .args([
+            "monitor",
+            "--session",
+            "--dest",
+            PORTAL_DEST,
+            "--object-path",
+            PORTAL_PATH,
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let Ok(mut child) = child else {
+        return;
+    };
+    let Some(stdout) = child.stdout.take() else {
+        return;
+    };
+
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if line.contains("SettingChanged") && line.contains("'color-scheme'") {
+                if let Some(scheme) = last_number(&line) {
+                    *CURRENT.lock().unwrap() = Some(from_scheme(scheme));
+                }
+            }
+        }
+
+        // Without the listener the cached value would go stale
+        *CURRENT.lock().unwrap() = None;
+        let _ = child.wait();
+    });
+}
+
+fn last_number(text: &str) -> Option<u32> {
+    text.trim()
+        .trim_end_matches([')', ',', '>'])
+        .rsplit(' ')
+        .next()
+        .and_then(|value| value.parse().ok())
+}
+
+fn from_scheme(scheme: u32) -> Appearance {
+    if scheme == 1 {
+        Appearance::Dark
+    } else {
+        Appearance::Light
+    }
+}
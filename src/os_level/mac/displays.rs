@@ -0,0 +1,61 @@
+use std::ffi::c_void;
+use std::ptr;
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::thread;
+
+// kCGDisplayBeginConfigurationFlag, set on the call made before a change happens
+const BEGIN_CONFIGURATION_FLAG: u32 = 1;
+
+type ReconfigurationCallback = extern "C" fn(display: u32, flags: u32, user_info: *mut c_void);
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGDisplayRegisterReconfigurationCallback(
+        callback: ReconfigurationCallback,
+        user_info: *mut c_void,
+    ) -> i32;
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFRunLoopRun();
+}
+
+// Where the callback reports to
+static NOTIFY: Mutex<Option<Sender<()>>> = Mutex::new(None);
+
+// Quartz calls back whenever a display is added, removed or reconfigured. The callbacks arrive
+// through a run loop, the command line has none, so a thread runs one for them.
+pub fn watch(notify: Sender<()>) -> Result<(), String> {
+    *NOTIFY.lock().unwrap() = Some(notify);
+
+    let (ready, started) = mpsc::channel();
+    thread::spawn(move || unsafe {
+        let error = CGDisplayRegisterReconfigurationCallback(reconfigured, ptr::null_mut());
+        let _ = ready.send(error);
+        if error == 0 {
+            CFRunLoopRun();
+        }
+    });
+
+    match started.recv() {
+        Ok(0) => Ok(()),
+        Ok(error) => Err(format!(
+            "Failed to watch for display changes: CoreGraphics error {}",
+            error
+        )),
+        Err(_) => Err("The display watcher stopped before it started".to_string()),
+    }
+}
+
+// Every display involved is reported twice, before and after the change. Only the second
+// one means the new layout can be read.
+extern "C" fn reconfigured(_display: u32, flags: u32, _user_info: *mut c_void) {
+    if flags & BEGIN_CONFIGURATION_FLAG != 0 {
+        return;
+    }
+    if let Some(notify) = &*NOTIFY.lock().unwrap() {
+        let _ = notify.send(());
+    }
+}
@@ -1,8 +1,12 @@
 use regex;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use crate::os_level::MonitorInfo;
+use crate::os_level::{Appearance, Insets, MonitorInfo};
+
+mod displays;
+
+pub use displays::watch as watch_displays;
 
 pub fn get_profile_info() -> Result<Vec<MonitorInfo>, String> {
     // Run "system_profiler SPDisplaysDataType" and capture output
@@ -82,14 +86,23 @@ pub fn get_profile_info() -> Result<Vec<MonitorInfo>, String> {
 
                         match (w, h) {
                             (Some(width), Some(height)) => {
+                                let name =
+                                    m.get("name").unwrap_or(&"Unknown".to_string()).to_string();
+                                // Built-in and identical external displays share a name, the
+                                // serial number tells them apart when macOS reports it
+                                let identity = match m.get("Display Serial Number") {
+                                    Some(serial) => format!("{} {}", name, serial),
+                                    None => name.clone(),
+                                };
                                 result.push(MonitorInfo {
                                     id: id + 1,
-                                    name: m
-                                        .get("name")
-                                        .unwrap_or(&"Unknown".to_string())
-                                        .to_string(),
+                                    name,
+                                    identity,
                                     width,
                                     height,
+                                    // system_profiler doesn't know about the menu bar and
+                                    // the Dock, their insets have to be configured
+                                    insets: Insets::default(),
                                 });
                             }
                             _ => {
@@ -115,7 +128,7 @@ pub fn get_profile_info() -> Result<Vec<MonitorInfo>, String> {
     }
 }
 
-pub fn set_background(absolute_path: &PathBuf, desktop_num: i32) {
+pub fn set_background(absolute_path: &Path, desktop_num: i32) -> Result<(), String> {
     let set_picture_script = format!(
         r#"tell application "System Events"
                 set picture of desktop {} to "{}"
@@ -131,14 +144,59 @@ pub fn set_background(absolute_path: &PathBuf, desktop_num: i32) {
     {
         Ok(output) => {
             if output.status.success() {
-                println!("  Monitor {} - Background set successfully", desktop_num);
+                Ok(())
             } else {
                 let error = String::from_utf8_lossy(&output.stderr);
-                eprintln!("  Monitor {} - Error: {}", desktop_num, error);
+                Err(format!("Error: {}", error.trim()))
             }
         }
-        Err(e) => {
-            eprintln!("  Monitor {} - Failed to execute: {}", desktop_num, e);
-        }
+        Err(e) => Err(format!("Failed to execute osascript: {}", e)),
+    }
+}
+
+pub fn get_background(desktop_num: i32) -> Result<PathBuf, String> {
+    let get_picture_script = format!(
+        r#"tell application "System Events"
+                get picture of desktop {}
+            end tell"#,
+        desktop_num
+    );
+
+    let output = match Command::new("osascript")
+        .arg("-e")
+        .arg(&get_picture_script)
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Failed to execute osascript: {}", e)),
+    };
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).to_string());
+    }
+
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if path.is_empty() {
+        return Err(format!("Monitor {} has no background picture", desktop_num));
+    }
+
+    Ok(PathBuf::from(path))
+}
+
+pub fn get_appearance() -> Result<Appearance, String> {
+    // The key only exists while dark mode is on, reading it fails otherwise
+    let output = match Command::new("defaults")
+        .args(["read", "-g", "AppleInterfaceStyle"])
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => return Err(format!("Failed to execute defaults: {}", e)),
+    };
+
+    let style = String::from_utf8_lossy(&output.stdout);
+    if output.status.success() && style.trim() == "Dark" {
+        Ok(Appearance::Dark)
+    } else {
+        Ok(Appearance::Light)
     }
 }
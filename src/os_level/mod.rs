@@ -1,20 +1,116 @@
-use std::path::PathBuf;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct MonitorInfo {
     pub name: String,
+    // Stays the same for a physical monitor even when the numbering or port changes
+    pub identity: String,
     pub id: usize,
     pub width: usize,
     pub height: usize,
+    // Covered by panels, docks or taskbars, empty when the backend can't tell
+    pub insets: Insets,
 }
 
+// How many pixels at each edge of a screen are hidden behind panels, docks or taskbars
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct Insets {
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+    pub left: u32,
+}
+
+// Written like CSS margins: "48" for all edges, "32 0" for top and bottom then left and right,
+// or "32 0 48 0" going clockwise from the top
+impl FromStr for Insets {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values: Option<Vec<u32>> = s.split_whitespace().map(|v| v.parse().ok()).collect();
+        match values.as_deref() {
+            Some(&[all]) => Ok(Insets {
+                top: all,
+                right: all,
+                bottom: all,
+                left: all,
+            }),
+            Some(&[vertical, horizontal]) => Ok(Insets {
+                top: vertical,
+                right: horizontal,
+                bottom: vertical,
+                left: horizontal,
+            }),
+            Some(&[top, right, bottom, left]) => Ok(Insets {
+                top,
+                right,
+                bottom,
+                left,
+            }),
+            _ => Err(format!(
+                "invalid insets '{}', expected pixels like '48', '32 0' or '32 0 48 0'",
+                s
+            )),
+        }
+    }
+}
+
+impl Insets {
+    pub fn scaled(&self, factor: f32) -> Insets {
+        let scale = |value: u32| (value as f32 * factor).round() as u32;
+        Insets {
+            top: scale(self.top),
+            right: scale(self.right),
+            bottom: scale(self.bottom),
+            left: scale(self.left),
+        }
+    }
+
+    // The part of a screen left visible as x, y, width and height. The whole screen when the
+    // insets would leave nothing.
+    pub fn visible_area(&self, screen_size: (u32, u32)) -> (u32, u32, u32, u32) {
+        let (width, height) = screen_size;
+        if self.left + self.right >= width || self.top + self.bottom >= height {
+            return (0, 0, width, height);
+        }
+        (
+            self.left,
+            self.top,
+            width - self.left - self.right,
+            height - self.top - self.bottom,
+        )
+    }
+}
+
+// Whether the system uses light or dark colors
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Appearance {
+    Light,
+    Dark,
+}
+
+// Whether set_background can show WebP images. Windows only can since Windows 11 and GNOME
+// only with an extra pixbuf loader, macOS always could.
+pub const SHOWS_WEBP: bool = cfg!(target_os = "macos");
+
+// Whether the system keeps a separate background for dark mode and switches to it by itself,
+// as GNOME does with picture-uri-dark. Elsewhere the background is rendered again when the
+// appearance changes.
+pub const HAS_DARK_SLOT: bool = cfg!(all(unix, not(target_os = "macos")));
+
 #[cfg(target_os = "macos")]
 mod mac;
 
+#[cfg(all(unix, not(target_os = "macos")))]
+mod linux;
+
 #[cfg(target_os = "windows")]
 mod win;
 
-fn get_profile_info() -> Result<Vec<MonitorInfo>, String> {
+pub fn get_profile_info() -> Result<Vec<MonitorInfo>, String> {
     #[cfg(target_os = "macos")]
     {
         return mac::get_profile_info();
@@ -23,6 +119,10 @@ fn get_profile_info() -> Result<Vec<MonitorInfo>, String> {
     {
         return win::get_profile_info();
     }
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        linux::get_profile_info()
+    }
 }
 
 pub fn get_monitor_count() -> Result<i32, String> {
@@ -44,13 +144,77 @@ pub fn get_monitor_size(monitor_num: i32) -> Result<(u32, u32), String> {
     Ok((monitor_info.width as u32, monitor_info.height as u32))
 }
 
-pub fn set_background(absolute_path: &PathBuf, desktop_num: i32) {
+pub fn set_background(absolute_path: &Path, desktop_num: i32) -> Result<(), String> {
+    #[cfg(target_os = "macos")]
+    {
+        return mac::set_background(absolute_path, desktop_num);
+    }
+    #[cfg(target_os = "windows")]
+    {
+        return win::set_background(absolute_path, desktop_num);
+    }
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        linux::set_background(absolute_path, desktop_num)
+    }
+}
+
+// Set the background shown in dark mode, only where HAS_DARK_SLOT
+pub fn set_dark_background(absolute_path: &Path, desktop_num: i32) -> Result<(), String> {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        linux::set_dark_background(absolute_path, desktop_num)
+    }
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    {
+        let _ = (absolute_path, desktop_num);
+        Err("This system has no separate dark mode background".to_string())
+    }
+}
+
+pub fn get_appearance() -> Result<Appearance, String> {
+    #[cfg(target_os = "macos")]
+    {
+        return mac::get_appearance();
+    }
+    #[cfg(target_os = "windows")]
+    {
+        return win::get_appearance();
+    }
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        linux::get_appearance()
+    }
+}
+
+pub fn get_background(desktop_num: i32) -> Result<PathBuf, String> {
+    #[cfg(target_os = "macos")]
+    {
+        return mac::get_background(desktop_num);
+    }
+    #[cfg(target_os = "windows")]
+    {
+        return win::get_background(desktop_num);
+    }
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        linux::get_background(desktop_num)
+    }
+}
+
+// Send on `notify` whenever the system reports that monitors were connected, disconnected or
+// reconfigured. Fails when the backend can't tell, leaving the caller to poll.
+pub fn watch_displays(notify: Sender<()>) -> Result<(), String> {
     #[cfg(target_os = "macos")]
     {
-        mac::set_background(absolute_path, desktop_num);
+        return mac::watch_displays(notify);
     }
     #[cfg(target_os = "windows")]
     {
-        win::set_background(absolute_path, desktop_num);
+        return win::watch_displays(notify);
+    }
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        linux::watch_displays(notify)
     }
 }
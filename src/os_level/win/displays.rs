@@ -0,0 +1,92 @@
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::thread;
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, RegisterClassW,
+    TranslateMessage, HMENU, MSG, WINDOW_EX_STYLE, WM_DISPLAYCHANGE, WNDCLASSW, WS_OVERLAPPED,
+};
+
+const WINDOW_CLASS: PCWSTR = w!("BackgroundManagerDisplayWatcher");
+
+// Where the window procedure reports to, it has no other way to reach the caller
+static NOTIFY: Mutex<Option<Sender<()>>> = Mutex::new(None);
+
+// Windows broadcasts WM_DISPLAYCHANGE to every top-level window when a monitor is connected,
+// disconnected or changes resolution. A hidden window on a thread of its own listens for it.
+pub fn watch(notify: Sender<()>) -> Result<(), String> {
+    *NOTIFY.lock().unwrap() = Some(notify);
+
+    let (ready, started) = mpsc::channel();
+    thread::spawn(move || unsafe {
+        let created = create_window();
+        let listening = created.is_ok();
+        let _ = ready.send(created);
+        if !listening {
+            return;
+        }
+
+        let mut message = MSG::default();
+        while GetMessageW(&mut message, None, 0, 0).as_bool() {
+            let _ = TranslateMessage(&message);
+            DispatchMessageW(&message);
+        }
+    });
+
+    match started.recv() {
+        Ok(created) => created,
+        Err(_) => Err("The display watcher stopped before it started".to_string()),
+    }
+}
+
+// Message-only windows don't get broadcasts, so this is a top-level window that is never shown
+unsafe fn create_window() -> Result<(), String> {
+    let instance = GetModuleHandleW(None)
+        .map_err(|e| format!("Failed to create the display watcher window: {}", e))?;
+
+    let class = WNDCLASSW {
+        lpfnWndProc: Some(window_proc),
+        hInstance: instance.into(),
+        lpszClassName: WINDOW_CLASS,
+        ..Default::default()
+    };
+    if RegisterClassW(&class) == 0 {
+        return Err(format!(
+            "Failed to register the display watcher window: {}",
+            windows::core::Error::from_win32()
+        ));
+    }
+
+    CreateWindowExW(
+        WINDOW_EX_STYLE::default(),
+        WINDOW_CLASS,
+        w!("Background Manager"),
+        WS_OVERLAPPED,
+        0,
+        0,
+        0,
+        0,
+        HWND::default(),
+        HMENU::default(),
+        instance,
+        None,
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to create the display watcher window: {}", e))
+}
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if message == WM_DISPLAYCHANGE {
+        if let Some(notify) = &*NOTIFY.lock().unwrap() {
+            let _ = notify.send(());
+        }
+    }
+    DefWindowProcW(window, message, wparam, lparam)
+}
@@ -1,14 +1,21 @@
-use crate::os_level::MonitorInfo;
-use windows::core::PCWSTR;
-use windows::Win32::Foundation::{BOOL, LPARAM, RECT};
+use crate::os_level::{Appearance, Insets, MonitorInfo};
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{BOOL, ERROR_SUCCESS, LPARAM, RECT};
 use windows::Win32::Graphics::Gdi::{
-    EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFOEXW,
+    EnumDisplayDevicesW, EnumDisplayMonitors, GetMonitorInfoW, DISPLAY_DEVICEW, HDC, HMONITOR,
+    MONITORINFOEXW,
 };
 use windows::Win32::System::Com::{
-    CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_ALL, COINIT_APARTMENTTHREADED,
+    CoCreateInstance, CoInitializeEx, CoTaskMemFree, CoUninitialize, CLSCTX_ALL,
+    COINIT_APARTMENTTHREADED,
 };
+use windows::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD};
 use windows::Win32::UI::Shell::{DesktopWallpaper, IDesktopWallpaper, DESKTOP_WALLPAPER_POSITION};
 
+mod displays;
+
+pub use displays::watch as watch_displays;
+
 pub fn get_profile_info() -> Result<Vec<MonitorInfo>, String> {
     let mut monitors: Vec<MonitorInfo> = Vec::new();
 
@@ -53,30 +60,74 @@ unsafe extern "system" fn monitor_enum_proc(
         let width = (rect.right - rect.left) as usize;
         let height = (rect.bottom - rect.top) as usize;
 
+        // The work area is what the taskbar and docked app bars leave free
+        let work = monitor_info.monitorInfo.rcWork;
+        let insets = Insets {
+            top: (work.top - rect.top).max(0) as u32,
+            right: (rect.right - work.right).max(0) as u32,
+            bottom: (rect.bottom - work.bottom).max(0) as u32,
+            left: (work.left - rect.left).max(0) as u32,
+        };
+
         let device_name = String::from_utf16_lossy(&monitor_info.szDevice)
             .trim_end_matches('\0')
             .to_string();
 
+        let identity =
+            get_monitor_identity(&monitor_info.szDevice).unwrap_or_else(|| device_name.clone());
+
         let id = monitors.len() + 1;
 
         monitors.push(MonitorInfo {
             name: device_name,
+            identity,
             id,
             width,
             height,
+            insets,
         });
     }
 
     true.into()
 }
 
-pub fn set_background(absolute_path: &std::path::PathBuf, desktop_num: i32) {
+// The adapter output name (\\.\DISPLAY1) changes when docking or swapping cables, the device
+// interface path of the monitor attached to it does not
+unsafe fn get_monitor_identity(adapter_name: &[u16; 32]) -> Option<String> {
+    let mut display_device = DISPLAY_DEVICEW {
+        cb: std::mem::size_of::<DISPLAY_DEVICEW>() as u32,
+        ..Default::default()
+    };
+
+    // EDD_GET_DEVICE_INTERFACE_NAME = 1
+    if !EnumDisplayDevicesW(
+        PCWSTR::from_raw(adapter_name.as_ptr()),
+        0,
+        &mut display_device,
+        1,
+    )
+    .as_bool()
+    {
+        return None;
+    }
+
+    let device_id = String::from_utf16_lossy(&display_device.DeviceID)
+        .trim_end_matches('\0')
+        .to_string();
+
+    if device_id.is_empty() {
+        None
+    } else {
+        Some(device_id)
+    }
+}
+
+pub fn set_background(absolute_path: &std::path::Path, desktop_num: i32) -> Result<(), String> {
     unsafe {
         // Initialize COM
         let hr = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
         if hr.is_err() {
-            eprintln!("Failed to initialize COM: {:?}", hr);
-            return;
+            return Err(format!("Failed to initialize COM: {:?}", hr));
         }
 
         // Ensure COM is uninitialized when we exit
@@ -89,19 +140,15 @@ pub fn set_background(absolute_path: &std::path::PathBuf, desktop_num: i32) {
         let desktop_wallpaper = match desktop_wallpaper {
             Ok(dw) => dw,
             Err(e) => {
-                eprintln!("Failed to create IDesktopWallpaper instance: {:?}", e);
-                return;
+                return Err(format!(
+                    "Failed to create IDesktopWallpaper instance: {:?}",
+                    e
+                ))
             }
         };
 
         // Get the monitor ID for the specified desktop number
-        let monitor_id = match get_monitor_id(&desktop_wallpaper, desktop_num) {
-            Ok(id) => id,
-            Err(e) => {
-                eprintln!("{}", e);
-                return;
-            }
-        };
+        let monitor_id = get_monitor_id(&desktop_wallpaper, desktop_num)?;
 
         // Convert the path to a wide string
         let path_str = absolute_path.to_string_lossy();
@@ -113,8 +160,7 @@ pub fn set_background(absolute_path: &std::path::PathBuf, desktop_num: i32) {
             PCWSTR::from_raw(monitor_id.as_ptr()),
             PCWSTR::from_raw(path_wide.as_ptr()),
         ) {
-            eprintln!("Failed to set wallpaper: {:?}", e);
-            return;
+            return Err(format!("Failed to set wallpaper: {:?}", e));
         }
 
         // Set the position to fill (DWPOS_FILL = 0)
@@ -122,7 +168,72 @@ pub fn set_background(absolute_path: &std::path::PathBuf, desktop_num: i32) {
             eprintln!("Failed to set wallpaper position: {:?}", e);
         }
 
-        println!("  Monitor {} - Wallpaper set successfully", desktop_num);
+        Ok(())
+    }
+}
+
+pub fn get_background(desktop_num: i32) -> Result<std::path::PathBuf, String> {
+    unsafe {
+        // Initialize COM
+        let hr = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+        if hr.is_err() {
+            return Err(format!("Failed to initialize COM: {:?}", hr));
+        }
+
+        // Ensure COM is uninitialized when we exit
+        let _com_guard = ComGuard;
+
+        let desktop_wallpaper: IDesktopWallpaper =
+            match CoCreateInstance(&DesktopWallpaper, None, CLSCTX_ALL) {
+                Ok(dw) => dw,
+                Err(e) => {
+                    return Err(format!(
+                        "Failed to create IDesktopWallpaper instance: {:?}",
+                        e
+                    ))
+                }
+            };
+
+        let monitor_id = get_monitor_id(&desktop_wallpaper, desktop_num)?;
+
+        let pwstr = match desktop_wallpaper.GetWallpaper(PCWSTR::from_raw(monitor_id.as_ptr())) {
+            Ok(pwstr) => pwstr,
+            Err(e) => return Err(format!("Failed to get wallpaper: {:?}", e)),
+        };
+
+        // The returned string is allocated by COM and must be released by us
+        let path = pwstr.to_string();
+        CoTaskMemFree(Some(pwstr.0 as *const _));
+
+        match path {
+            Ok(path) if !path.is_empty() => Ok(std::path::PathBuf::from(path)),
+            Ok(_) => Err(format!("Monitor {} has no wallpaper", desktop_num)),
+            Err(e) => Err(format!("Failed to decode wallpaper path: {}", e)),
+        }
+    }
+}
+
+pub fn get_appearance() -> Result<Appearance, String> {
+    let mut light: u32 = 1;
+    let mut size = std::mem::size_of::<u32>() as u32;
+
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            w!("Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize"),
+            w!("AppsUseLightTheme"),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut light as *mut u32 as *mut _),
+            Some(&mut size),
+        )
+    };
+
+    // Windows versions without dark mode don't have the value
+    if result != ERROR_SUCCESS || light != 0 {
+        Ok(Appearance::Light)
+    } else {
+        Ok(Appearance::Dark)
     }
 }
 
@@ -0,0 +1,170 @@
+use ab_glyph::FontVec;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{Datelike, Local, NaiveDate};
+use photon_rs::PhotonImage;
+use rand::seq::SliceRandom;
+use std::fs;
+use std::path::Path;
+
+use crate::config::{expand_path, OverlayConfig};
+use crate::image_proc::{self, Anchor, TextStyle};
+use crate::os_level::Insets;
+use crate::photos;
+
+pub const KINDS: [&str; 4] = ["caption", "date", "quote", "calendar"];
+
+const DEFAULT_SIZE: f32 = 32.0;
+const DEFAULT_MARGIN: f32 = 48.0;
+const DEFAULT_COLOR: (u8, u8, u8) = (255, 255, 255);
+const DEFAULT_DATE_FORMAT: &str = "%A, %e %B";
+
+pub fn load_font(path: &Path) -> Result<FontVec, String> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) => return Err(format!("Failed to read '{}': {}", path.display(), e)),
+    };
+    FontVec::try_from_vec(data)
+        .map_err(|e| format!("'{}' is not a usable font: {}", path.display(), e))
+}
+
+// Formatting a date with an invalid format panics, so it has to be checked first
+pub fn valid_date_format(format: &str) -> bool {
+    StrftimeItems::new(format).all(|item| !matches!(item, Item::Error))
+}
+
+// Draw the overlay onto an image rendered from the source, inside the area the insets leave
+pub fn draw(
+    image: &mut PhotonImage,
+    overlay: &OverlayConfig,
+    source: &Path,
+    insets: Insets,
+) -> Result<(), String> {
+    let rows = rows(overlay, source, Local::now().date_naive())?;
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let style = TextStyle {
+        font: load_font(&expand_path(&overlay.font))?,
+        size: overlay.size.unwrap_or(DEFAULT_SIZE),
+        color: overlay
+            .color
+            .as_deref()
+            .and_then(image_proc::parse_color)
+            .unwrap_or(DEFAULT_COLOR),
+        shadow: overlay.shadow.unwrap_or(true),
+        anchor: overlay
+            .anchor
+            .as_deref()
+            .and_then(|a| a.parse().ok())
+            .unwrap_or(Anchor::BottomRight),
+        margin: overlay.margin.unwrap_or(DEFAULT_MARGIN),
+        insets,
+    };
+    image_proc::draw_text(image, &rows, &style);
+    Ok(())
+}
+
+// The same overlay for an image scaled by the factor, so previews keep their proportions
+pub fn scaled(overlay: &OverlayConfig, factor: f32) -> OverlayConfig {
+    OverlayConfig {
+        size: Some(overlay.size.unwrap_or(DEFAULT_SIZE) * factor),
+        margin: Some(overlay.margin.unwrap_or(DEFAULT_MARGIN) * factor),
+        ..overlay.clone()
+    }
+}
+
+// The text to show as rows of cells, see image_proc::draw_text
+fn rows(
+    overlay: &OverlayConfig,
+    source: &Path,
+    today: NaiveDate,
+) -> Result<Vec<Vec<String>>, String> {
+    let lines = match overlay.show.as_str() {
+        "caption" => vec![caption(source)],
+        "date" => {
+            let format = overlay.format.as_deref().unwrap_or(DEFAULT_DATE_FORMAT);
+            if !valid_date_format(format) {
+                return Err(format!("Invalid date format '{}'", format));
+            }
+            vec![today.format(format).to_string()]
+        }
+        "quote" => match &overlay.file {
+            Some(file) => quote(&expand_path(file))?,
+            None => return Err("Quote overlays need a file".to_string()),
+        },
+        "calendar" => return Ok(calendar(today)),
+        other => return Err(format!("Unknown overlay '{}'", other)),
+    };
+
+    Ok(lines
+        .into_iter()
+        .map(|line| vec![line.trim().to_string()])
+        .collect())
+}
+
+// The EXIF description, or else the file name with separators turned into spaces
+fn caption(source: &Path) -> String {
+    photos::description(source).unwrap_or_else(|| {
+        source
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().replace(['_', '-'], " "))
+            .unwrap_or_default()
+    })
+}
+
+// A random quote from a file where they are separated by empty lines, one row per line
+fn quote(path: &Path) -> Result<Vec<String>, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => return Err(format!("Failed to read '{}': {}", path.display(), e)),
+    };
+
+    let mut quotes: Vec<Vec<String>> = Vec::new();
+    let mut current = Vec::new();
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() {
+            if !current.is_empty() {
+                quotes.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(line.to_string());
+        }
+    }
+    if !current.is_empty() {
+        quotes.push(current);
+    }
+
+    Ok(quotes
+        .choose(&mut rand::thread_rng())
+        .cloned()
+        .unwrap_or_default())
+}
+
+// The month as a title, a row of weekdays starting on Monday and one row per week
+fn calendar(today: NaiveDate) -> Vec<Vec<String>> {
+    let mut rows = vec![
+        vec![today.format("%B %Y").to_string()],
+        ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"]
+            .iter()
+            .map(|day| day.to_string())
+            .collect(),
+    ];
+
+    let first = today.with_day(1).unwrap_or(today);
+    let mut week = vec![String::new(); first.weekday().num_days_from_monday() as usize];
+    for day in first
+        .iter_days()
+        .take_while(|day| day.month() == first.month())
+    {
+        week.push(day.day().to_string());
+        if week.len() == 7 {
+            rows.push(std::mem::take(&mut week));
+        }
+    }
+    if !week.is_empty() {
+        week.resize(7, String::new());
+        rows.push(week);
+    }
+    rows
+}
@@ -0,0 +1,276 @@
+use serde::Serialize;
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::decode;
+use crate::state;
+
+// Images are shrunk to at most this many pixels per side before their colors are counted
+const SAMPLE_SIZE: u32 = 128;
+
+// How many colors median cut splits an image into
+const PALETTE_SIZE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Color(pub u8, pub u8, pub u8);
+
+impl Color {
+    pub fn hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+
+    // Perceived brightness from 0 to 255
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.0 as f64 + 0.7152 * self.1 as f64 + 0.0722 * self.2 as f64
+    }
+
+    // Move towards white by a positive amount up to 1, or towards black by a negative one
+    pub fn shade(&self, amount: f64) -> Color {
+        let channel = |c: u8| {
+            let c = c as f64;
+            let target = if amount > 0.0 { 255.0 } else { 0.0 };
+            (c + (target - c) * amount.abs()).round() as u8
+        };
+        Color(channel(self.0), channel(self.1), channel(self.2))
+    }
+}
+
+// The main colors of an image, most common first.
+#[derive(Debug, Clone, Serialize)]
+pub struct Palette {
+    pub source: PathBuf,
+    pub colors: Vec<PaletteColor>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PaletteColor {
+    pub hex: String,
+    // How much of the image it covers, from 0 to 1.
+    pub share: f64,
+    #[serde(skip)]
+    pub color: Color,
+}
+
+// A terminal color scheme made from a palette: 16 colors, darkest first, as pywal arranges them.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub background: Color,
+    pub foreground: Color,
+    pub cursor: Color,
+    pub colors: [Color; 16],
+}
+
+pub fn palette_dir() -> Result<PathBuf, String> {
+    Ok(state::data_dir()?.join("palette"))
+}
+
+// Where the palette of the image on one monitor is written, for the hooks of that monitor
+pub fn monitor_palette_dir(desktop_num: i32) -> Result<PathBuf, String> {
+    Ok(palette_dir()?
+        .join("monitors")
+        .join(desktop_num.to_string()))
+}
+
+// Median cut: keep splitting the group of pixels with the widest spread in one channel at its
+// median, then average each group
+pub fn extract(path: &Path) -> Result<Palette, String> {
+    let img = decode::open(path, None)?;
+
+    let (width, height) = (img.get_width(), img.get_height());
+    let scale = (SAMPLE_SIZE as f64 / width.max(height) as f64).min(1.0);
+    let small = photon_rs::transform::resize(
+        &img,
+        ((width as f64 * scale) as u32).max(1),
+        ((height as f64 * scale) as u32).max(1),
+        photon_rs::transform::SamplingFilter::Triangle,
+    );
+
+    // Mostly transparent pixels don't show on the desktop
+    let pixels: Vec<[u8; 3]> = small
+        .get_raw_pixels()
+        .chunks(4)
+        .filter(|p| p[3] >= 128)
+        .map(|p| [p[0], p[1], p[2]])
+        .collect();
+    if pixels.is_empty() {
+        return Err(format!("'{}' has no visible pixels", path.display()));
+    }
+
+    let total = pixels.len() as f64;
+    let mut groups = vec![pixels];
+    while groups.len() < PALETTE_SIZE {
+        let widest = groups
+            .iter()
+            .enumerate()
+            .map(|(i, group)| {
+                let (channel, spread) = widest_channel(group);
+                (i, channel, spread)
+            })
+            .max_by_key(|(_, _, spread)| *spread);
+
+        let Some((i, channel, spread)) = widest else {
+            break;
+        };
+        if spread == 0 {
+            break;
+        }
+
+        let mut group = groups.swap_remove(i);
+        group.sort_unstable_by_key(|p| p[channel]);
+        let upper = group.split_off(group.len() / 2);
+        groups.push(group);
+        groups.push(upper);
+    }
+
+    groups.sort_by_key(|group| std::cmp::Reverse(group.len()));
+    let colors = groups
+        .iter()
+        .map(|group| {
+            let color = average(group);
+            PaletteColor {
+                hex: color.hex(),
+                share: group.len() as f64 / total,
+                color,
+            }
+        })
+        .collect();
+
+    Ok(Palette {
+        source: path.to_path_buf(),
+        colors,
+    })
+}
+
+// The channel whose values spread the most, and by how much
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = pixels.iter().map(|p| p[channel]);
+            let spread = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, spread)
+        })
+        .max_by_key(|(_, spread)| *spread)
+        .unwrap_or((0, 0))
+}
+
+fn average(pixels: &[[u8; 3]]) -> Color {
+    let mut sums = [0u64; 3];
+    for p in pixels {
+        for (sum, value) in sums.iter_mut().zip(p) {
+            *sum += *value as u64;
+        }
+    }
+    let count = pixels.len().max(1) as u64;
+    Color(
+        (sums[0] / count) as u8,
+        (sums[1] / count) as u8,
+        (sums[2] / count) as u8,
+    )
+}
+
+impl Palette {
+    // The darkest color becomes the background and the lightest the foreground, both pushed
+    // further apart so text stays readable. The rest fill the colors in between.
+    pub fn theme(&self) -> Theme {
+        let mut colors: Vec<Color> = self.colors.iter().map(|c| c.color).collect();
+        colors.sort_by(|a, b| a.luminance().total_cmp(&b.luminance()));
+        while colors.len() < PALETTE_SIZE {
+            colors.push(*colors.last().unwrap_or(&Color(0x80, 0x80, 0x80)));
+        }
+
+        let background = colors[0].shade(-0.6);
+        let foreground = colors[PALETTE_SIZE - 1].shade(0.7);
+
+        let mut theme = [background; 16];
+        theme[1..7].copy_from_slice(&colors[1..7]);
+        theme[7] = foreground;
+        theme[8] = background.shade(0.25);
+        theme[9..15].copy_from_slice(&colors[1..7]);
+        theme[15] = foreground;
+
+        Theme {
+            background,
+            foreground,
+            cursor: foreground,
+            colors: theme,
+        }
+    }
+
+    // Write palette.json, a pywal colors.json, colors.Xresources and colors.css into the folder
+    pub fn export(&self, dir: &Path) -> Result<Vec<PathBuf>, String> {
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Err(format!("Failed to create '{}': {}", dir.display(), e));
+        }
+
+        let theme = self.theme();
+        let palette = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize the palette: {}", e))?;
+        let files = [
+            ("palette.json", palette),
+            ("colors.json", theme.pywal(&self.source)),
+            ("colors.Xresources", theme.xresources()),
+            ("colors.css", theme.css()),
+        ];
+
+        let mut written = Vec::new();
+        for (name, contents) in files {
+            let path = dir.join(name);
+            if let Err(e) = fs::write(&path, contents) {
+                return Err(format!("Failed to write '{}': {}", path.display(), e));
+            }
+            written.push(path);
+        }
+        Ok(written)
+    }
+}
+
+impl Theme {
+    // The colors.json pywal writes, which many other tools read
+    pub fn pywal(&self, wallpaper: &Path) -> String {
+        let colors: serde_json::Map<String, serde_json::Value> = self
+            .colors
+            .iter()
+            .enumerate()
+            .map(|(i, color)| (format!("color{}", i), json!(color.hex())))
+            .collect();
+
+        let theme = json!({
+            "wallpaper": wallpaper.to_string_lossy(),
+            "alpha": "100",
+            "special": {
+                "background": self.background.hex(),
+                "foreground": self.foreground.hex(),
+                "cursor": self.cursor.hex(),
+            },
+            "colors": colors,
+        });
+        serde_json::to_string_pretty(&theme).unwrap_or_default()
+    }
+
+    pub fn xresources(&self) -> String {
+        let mut lines = vec![
+            format!("*.background: {}", self.background.hex()),
+            format!("*.foreground: {}", self.foreground.hex()),
+            format!("*.cursorColor: {}", self.cursor.hex()),
+        ];
+        for (i, color) in self.colors.iter().enumerate() {
+            lines.push(format!("*.color{}: {}", i, color.hex()));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    pub fn css(&self) -> String {
+        let mut lines = vec![
+            ":root {".to_string(),
+            format!("  --background: {};", self.background.hex()),
+            format!("  --foreground: {};", self.foreground.hex()),
+            format!("  --cursor: {};", self.cursor.hex()),
+        ];
+        for (i, color) in self.colors.iter().enumerate() {
+            lines.push(format!("  --color{}: {};", i, color.hex()));
+        }
+        lines.push("}".to_string());
+        lines.join("\n") + "\n"
+    }
+}
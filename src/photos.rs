@@ -0,0 +1,270 @@
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::state;
+
+// How many days before or after today count as the same week
+const WEEK_WINDOW: i64 = 3;
+
+// Capture dates of photos, so EXIF data is only read again for new or changed files. Stored
+// as JSON in the user data directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PhotoIndex {
+    #[serde(default)]
+    pub photos: BTreeMap<PathBuf, IndexedPhoto>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedPhoto {
+    // Modification time of the file when it was indexed, in seconds since the UNIX epoch.
+    pub modified: u64,
+    // When the photo was taken, missing for images without EXIF dates.
+    pub taken: Option<NaiveDate>,
+}
+
+fn index_path() -> Result<PathBuf, String> {
+    Ok(state::data_dir()?.join("photos.json"))
+}
+
+impl PhotoIndex {
+    pub fn load() -> Result<PhotoIndex, String> {
+        let path = index_path()?;
+        if !path.exists() {
+            return Ok(PhotoIndex::default());
+        }
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => return Err(format!("Failed to read '{}': {}", path.display(), e)),
+        };
+
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse '{}': {}", path.display(), e))
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = index_path()?;
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize the photo index: {}", e))?;
+
+        fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write '{}': {}", path.display(), e))
+    }
+
+    // Reads the capture date of every image that is new or changed since it was indexed.
+    // Returns how many were read.
+    pub fn update(&mut self, images: &[PathBuf]) -> usize {
+        let mut read = 0;
+        for path in images {
+            let modified = modified_secs(path);
+            if let Some(indexed) = self.photos.get(path) {
+                if indexed.modified == modified {
+                    continue;
+                }
+            }
+
+            self.photos.insert(
+                path.clone(),
+                IndexedPhoto {
+                    modified,
+                    taken: capture_date(path),
+                },
+            );
+            read += 1;
+        }
+        read
+    }
+
+    pub fn taken(&self, path: &Path) -> Option<NaiveDate> {
+        self.photos.get(path).and_then(|photo| photo.taken)
+    }
+}
+
+fn modified_secs(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+// The date the camera recorded, falling back to when the file was last edited in camera
+pub fn capture_date(path: &Path) -> Option<NaiveDate> {
+    let file = File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+
+    [exif::Tag::DateTimeOriginal, exif::Tag::DateTime]
+        .iter()
+        .filter_map(|tag| exif.get_field(*tag, exif::In::PRIMARY))
+        .find_map(|field| match &field.value {
+            exif::Value::Ascii(values) => values.first().and_then(|value| parse_exif_date(value)),
+            _ => None,
+        })
+}
+
+// The caption written into the photo, if any
+pub fn description(path: &Path) -> Option<String> {
+    let file = File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+
+    let field = exif.get_field(exif::Tag::ImageDescription, exif::In::PRIMARY)?;
+    match &field.value {
+        exif::Value::Ascii(values) => values
+            .iter()
+            .map(|value| String::from_utf8_lossy(value).trim().to_string())
+            .find(|text| !text.is_empty()),
+        _ => None,
+    }
+}
+
+// EXIF dates look like "2019:07:14 18:03:51"
+fn parse_exif_date(value: &[u8]) -> Option<NaiveDate> {
+    let text = std::str::from_utf8(value).ok()?;
+    let date = text.get(..10)?;
+    NaiveDate::parse_from_str(date, "%Y:%m:%d").ok()
+}
+
+// Photos taken on the same calendar day in earlier years. When there are none, those taken
+// within three days of it in an earlier year. Empty when neither has any.
+pub fn on_this_day(images: &[PathBuf], index: &PhotoIndex, today: NaiveDate) -> Vec<PathBuf> {
+    let earlier: Vec<(&PathBuf, NaiveDate)> = images
+        .iter()
+        .filter_map(|path| index.taken(path).map(|taken| (path, taken)))
+        .filter(|(_, taken)| taken.year() < today.year())
+        .collect();
+
+    let same_day: Vec<PathBuf> = earlier
+        .iter()
+        .filter(|(_, taken)| taken.month() == today.month() && taken.day() == today.day())
+        .map(|(path, _)| (*path).clone())
+        .collect();
+    if !same_day.is_empty() {
+        return same_day;
+    }
+
+    earlier
+        .iter()
+        .filter(|(_, taken)| same_week(*taken, today))
+        .map(|(path, _)| (*path).clone())
+        .collect()
+}
+
+// Whether the day of the year is within the window around today, wrapping around the end of the
+// year so 30 December and 2 January are close. Week numbers can't tell, those two can be in
+// week 1 and week 53.
+fn same_week(taken: NaiveDate, today: NaiveDate) -> bool {
+    (today.year() - 1..=today.year() + 1).any(|year| {
+        // 29 February is the 28th in other years
+        let day = NaiveDate::from_ymd_opt(year, taken.month(), taken.day())
+            .or_else(|| NaiveDate::from_ymd_opt(year, 2, 28));
+        day.is_some_and(|day| (day - today).num_days().abs() <= WEEK_WINDOW)
+    })
+}
+
+// Narrow the images down to those taken on this day in earlier years, keeping all of them when
+// none were. The index is updated and saved on the way.
+pub fn select_on_this_day(images: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut index = match PhotoIndex::load() {
+        Ok(index) => index,
+        Err(e) => {
+            eprintln!("Warning: {}", e);
+            PhotoIndex::default()
+        }
+    };
+
+    if index.update(&images) > 0 {
+        if let Err(e) = index.save() {
+            eprintln!("Warning: {}", e);
+        }
+    }
+
+    let today = chrono::Local::now().date_naive();
+    let selected = on_this_day(&images, &index, today);
+    if selected.is_empty() {
+        return images;
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    // An index of photos named after the day they were taken
+    fn index_of(dates: &[NaiveDate]) -> (Vec<PathBuf>, PhotoIndex) {
+        let mut index = PhotoIndex::default();
+        let mut images = Vec::new();
+        for taken in dates {
+            let path = PathBuf::from(format!("{}.jpg", taken));
+            index.photos.insert(
+                path.clone(),
+                IndexedPhoto {
+                    modified: 0,
+                    taken: Some(*taken),
+                },
+            );
+            images.push(path);
+        }
+        (images, index)
+    }
+
+    fn names(paths: &[PathBuf]) -> Vec<String> {
+        paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn prefers_the_same_day() {
+        let (images, index) = index_of(&[date(2019, 7, 14), date(2020, 7, 15), date(2021, 7, 14)]);
+        let selected = on_this_day(&images, &index, date(2024, 7, 14));
+        assert_eq!(names(&selected), ["2019-07-14.jpg", "2021-07-14.jpg"]);
+    }
+
+    #[test]
+    fn falls_back_to_three_days_around() {
+        let (images, index) = index_of(&[date(2019, 7, 11), date(2020, 7, 17), date(2021, 7, 18)]);
+        let selected = on_this_day(&images, &index, date(2024, 7, 14));
+        assert_eq!(names(&selected), ["2019-07-11.jpg", "2020-07-17.jpg"]);
+    }
+
+    #[test]
+    fn window_wraps_around_the_year_end() {
+        let (images, index) = index_of(&[date(2019, 12, 30), date(2020, 12, 27)]);
+        let selected = on_this_day(&images, &index, date(2024, 1, 2));
+        assert_eq!(names(&selected), ["2019-12-30.jpg"]);
+
+        let (images, index) = index_of(&[date(2020, 1, 2)]);
+        let selected = on_this_day(&images, &index, date(2023, 12, 30));
+        assert_eq!(names(&selected), ["2020-01-02.jpg"]);
+    }
+
+    #[test]
+    fn leap_day_counts_as_the_28th() {
+        let (images, index) = index_of(&[date(2020, 2, 29)]);
+        let selected = on_this_day(&images, &index, date(2023, 3, 3));
+        assert_eq!(names(&selected), ["2020-02-29.jpg"]);
+    }
+
+    #[test]
+    fn ignores_this_year_and_undated_photos() {
+        let (mut images, index) = index_of(&[date(2024, 7, 14)]);
+        images.push(PathBuf::from("undated.jpg"));
+        assert!(on_this_day(&images, &index, date(2024, 7, 14)).is_empty());
+    }
+}
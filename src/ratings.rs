@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::state;
+
+pub const MAX_STARS: u8 = 5;
+
+// Unrated images count as average
+const UNRATED_STARS: u8 = 3;
+
+// What the user thinks of images, keyed by their absolute path. Stored as JSON in the user
+// data directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Ratings {
+    #[serde(default)]
+    pub images: BTreeMap<PathBuf, ImageRating>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImageRating {
+    #[serde(default)]
+    pub favorite: bool,
+    // Banned images are never picked for rotation.
+    #[serde(default)]
+    pub banned: bool,
+    // From 1 to `MAX_STARS`, missing when not rated.
+    #[serde(default)]
+    pub stars: Option<u8>,
+}
+
+fn ratings_path() -> Result<PathBuf, String> {
+    Ok(state::data_dir()?.join("ratings.json"))
+}
+
+impl Ratings {
+    pub fn load() -> Result<Ratings, String> {
+        let path = ratings_path()?;
+        if !path.exists() {
+            return Ok(Ratings::default());
+        }
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => return Err(format!("Failed to read '{}': {}", path.display(), e)),
+        };
+
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse '{}': {}", path.display(), e))
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = ratings_path()?;
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize the ratings: {}", e))?;
+
+        fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write '{}': {}", path.display(), e))
+    }
+
+    // Looks the image up by the path given, then by its absolute path.
+    pub fn get(&self, path: &Path) -> Option<&ImageRating> {
+        if self.images.is_empty() {
+            return None;
+        }
+
+        self.images.get(path).or_else(|| {
+            path.canonicalize()
+                .ok()
+                .and_then(|absolute| self.images.get(&absolute))
+        })
+    }
+
+    // Changes the rating of an image, forgetting images that end up without any.
+    pub fn update(&mut self, path: &Path, change: impl FnOnce(&mut ImageRating)) {
+        let rating = self.images.entry(path.to_path_buf()).or_default();
+        change(rating);
+        if *rating == ImageRating::default() {
+            self.images.remove(path);
+        }
+    }
+
+    pub fn is_banned(&self, path: &Path) -> bool {
+        self.get(path).is_some_and(|rating| rating.banned)
+    }
+
+    // How likely the image is to be picked compared to others. Stars count linearly, a
+    // favorite counts double and a banned image not at all.
+    pub fn weight(&self, path: &Path) -> f64 {
+        let rating = match self.get(path) {
+            Some(rating) => rating,
+            None => return UNRATED_STARS as f64,
+        };
+
+        if rating.banned {
+            return 0.0;
+        }
+
+        let stars = rating.stars.unwrap_or(UNRATED_STARS) as f64;
+        if rating.favorite {
+            stars * 2.0
+        } else {
+            stars
+        }
+    }
+}
@@ -0,0 +1,842 @@
+use serde::Deserialize;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+use crate::config::RemoteConfig;
+use crate::library::{self, Library, LibraryEntry};
+use crate::sources;
+
+const BING_URL: &str = "https://www.bing.com";
+const APOD_URL: &str = "https://api.nasa.gov/planetary/apod";
+const APOD_DEMO_KEY: &str = "DEMO_KEY";
+
+// Bing keeps the last 8 images of the day
+const BING_MAX_COUNT: usize = 8;
+const FEED_DEFAULT_COUNT: usize = 10;
+
+// Larger downloads are refused, nothing a wallpaper needs is that big
+const MAX_DOWNLOAD: u64 = 64 * 1024 * 1024;
+
+pub const PROVIDERS: &[&str] = &["bing", "apod", "json-feed", "rss"];
+
+// Everything the providers need from HTTP. Pass another implementation to talk to a local
+// stand-in server instead of the real services.
+pub trait HttpClient {
+    fn get(&self, url: &str) -> Result<Vec<u8>, String>;
+}
+
+pub struct UreqClient {
+    agent: ureq::Agent,
+}
+
+impl UreqClient {
+    pub fn new() -> UreqClient {
+        UreqClient {
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .user_agent(concat!("background_manager/", env!("CARGO_PKG_VERSION")))
+                .build(),
+        }
+    }
+}
+
+impl Default for UreqClient {
+    fn default() -> Self {
+        UreqClient::new()
+    }
+}
+
+impl HttpClient for UreqClient {
+    fn get(&self, url: &str) -> Result<Vec<u8>, String> {
+        let response = match self.agent.get(url).call() {
+            Ok(response) => response,
+            Err(e) => return Err(format!("Failed to fetch '{}': {}", url, e)),
+        };
+
+        // One byte more than allowed is enough to tell that it's too large
+        let mut bytes = Vec::new();
+        match response
+            .into_reader()
+            .take(MAX_DOWNLOAD + 1)
+            .read_to_end(&mut bytes)
+        {
+            Ok(_) if bytes.len() as u64 > MAX_DOWNLOAD => Err(format!(
+                "Failed to fetch '{}': larger than {} MB",
+                url,
+                MAX_DOWNLOAD / 1024 / 1024
+            )),
+            Ok(_) => Ok(bytes),
+            Err(e) => Err(format!("Failed to read '{}': {}", url, e)),
+        }
+    }
+}
+
+// An image offered by a provider, not downloaded yet
+#[derive(Debug, Clone, Default)]
+pub struct RemoteImage {
+    pub url: String,
+    pub title: String,
+    pub attribution: String,
+    pub page_url: Option<String>,
+    pub date: Option<String>,
+}
+
+pub trait RemoteProvider {
+    // Used for the library folder and in messages
+    fn name(&self) -> &str;
+
+    // The images currently offered, newest first
+    fn list(&self, http: &dyn HttpClient) -> Result<Vec<RemoteImage>, String>;
+}
+
+pub fn provider_from_config(config: &RemoteConfig) -> Result<Box<dyn RemoteProvider>, String> {
+    let feed_url = || match &config.url {
+        Some(url) => Ok(url.clone()),
+        None => Err(format!("the '{}' provider needs a url", config.provider)),
+    };
+
+    match config.provider.as_str() {
+        "bing" => Ok(Box::new(Bing {
+            base_url: config.url.clone().unwrap_or_else(|| BING_URL.to_string()),
+            market: config.market.clone().unwrap_or_else(|| "en-US".to_string()),
+            count: config.count.unwrap_or(1).min(BING_MAX_COUNT),
+        })),
+        "apod" => Ok(Box::new(Apod {
+            base_url: config.url.clone().unwrap_or_else(|| APOD_URL.to_string()),
+            api_key: config
+                .api_key
+                .clone()
+                .unwrap_or_else(|| APOD_DEMO_KEY.to_string()),
+            count: config.count.unwrap_or(1),
+        })),
+        "json-feed" => Ok(Box::new(JsonFeed {
+            url: feed_url()?,
+            count: config.count.unwrap_or(FEED_DEFAULT_COUNT),
+        })),
+        "rss" => Ok(Box::new(RssFeed {
+            url: feed_url()?,
+            count: config.count.unwrap_or(FEED_DEFAULT_COUNT),
+        })),
+        other => Err(format!(
+            "unknown provider '{}', expected one of: {}",
+            other,
+            PROVIDERS.join(", ")
+        )),
+    }
+}
+
+// Download what the provider offers into the library, skipping images that are already there.
+// Returns the entries that were added.
+pub fn fetch(
+    provider: &dyn RemoteProvider,
+    http: &dyn HttpClient,
+    library: &mut Library,
+) -> Result<Vec<LibraryEntry>, String> {
+    let dir = library::library_dir()?.join(provider.name());
+    fetch_into(provider, http, library, &dir)
+}
+
+fn fetch_into(
+    provider: &dyn RemoteProvider,
+    http: &dyn HttpClient,
+    library: &mut Library,
+    dir: &Path,
+) -> Result<Vec<LibraryEntry>, String> {
+    if let Err(e) = fs::create_dir_all(dir) {
+        return Err(format!("Failed to create '{}': {}", dir.display(), e));
+    }
+
+    let mut added = Vec::new();
+    for image in provider.list(http)? {
+        if library.find_by_url(&image.url).is_some() {
+            continue;
+        }
+
+        let bytes = match http.get(&image.url) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("  {} - {}", provider.name(), e);
+                continue;
+            }
+        };
+
+        // Feeds link to all kinds of things, only keep what really is an image
+        if imagesize::blob_size(&bytes).is_err() {
+            eprintln!(
+                "  {} - '{}' is not an image, skipping",
+                provider.name(),
+                image.url
+            );
+            continue;
+        }
+
+        let path = unused_path(dir, &file_name_for(&image.url));
+        if let Err(e) = fs::write(&path, &bytes) {
+            eprintln!(
+                "  {} - Failed to write '{}': {}",
+                provider.name(),
+                path.display(),
+                e
+            );
+            continue;
+        }
+
+        let entry = LibraryEntry {
+            path,
+            provider: provider.name().to_string(),
+            title: image.title,
+            attribution: image.attribution,
+            source_url: image.url,
+            page_url: image.page_url,
+            date: image.date,
+            added: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0),
+        };
+        library.add(entry.clone());
+        added.push(entry);
+    }
+
+    Ok(added)
+}
+
+// The last path segment when it looks like an image, otherwise a query value that does (Bing
+// puts the name in `id`), otherwise a hash of the URL
+fn file_name_for(url: &str) -> String {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+
+    let candidates = path.rsplit('/').take(1).chain(
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('=').map(|(_, v)| v)),
+    );
+
+    for candidate in candidates {
+        let name = sanitize(candidate);
+        if sources::is_image(Path::new(&name)) {
+            return name;
+        }
+    }
+
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    format!("{:016x}.jpg", hasher.finish())
+}
+
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_string()
+}
+
+// Providers reuse generic names like "image.jpg", number them instead of overwriting
+fn unused_path(dir: &Path, file_name: &str) -> PathBuf {
+    let path = dir.join(file_name);
+    if !path.exists() {
+        return path;
+    }
+
+    let (stem, extension) = file_name.rsplit_once('.').unwrap_or((file_name, ""));
+    (2..)
+        .map(|i| dir.join(format!("{}_{}.{}", stem, i, extension)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+fn get_json<T: for<'de> Deserialize<'de>>(http: &dyn HttpClient, url: &str) -> Result<T, String> {
+    let bytes = http.get(url)?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("Unexpected response from '{}': {}", url, e))
+}
+
+// Relative links in a response are relative to the address it came from, the way a browser
+// would resolve them
+fn absolute_url(base: &str, url: &str) -> String {
+    match Url::parse(base).and_then(|base| base.join(url)) {
+        Ok(absolute) => absolute.to_string(),
+        Err(_) => url.to_string(),
+    }
+}
+
+// Bing image of the day
+pub struct Bing {
+    pub base_url: String,
+    pub market: String,
+    pub count: usize,
+}
+
+#[derive(Deserialize)]
+struct BingArchive {
+    images: Vec<BingImage>,
+}
+
+#[derive(Deserialize)]
+struct BingImage {
+    url: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    copyright: String,
+    copyrightlink: Option<String>,
+    startdate: Option<String>,
+}
+
+impl RemoteProvider for Bing {
+    fn name(&self) -> &str {
+        "bing"
+    }
+
+    fn list(&self, http: &dyn HttpClient) -> Result<Vec<RemoteImage>, String> {
+        let url = format!(
+            "{}/HPImageArchive.aspx?format=js&idx=0&n={}&mkt={}",
+            self.base_url.trim_end_matches('/'),
+            self.count,
+            self.market
+        );
+        let archive: BingArchive = get_json(http, &url)?;
+
+        Ok(archive
+            .images
+            .into_iter()
+            .map(|image| RemoteImage {
+                url: absolute_url(&self.base_url, &image.url),
+                title: image.title,
+                attribution: image.copyright,
+                page_url: image
+                    .copyrightlink
+                    .filter(|link| !link.is_empty() && link != "javascript:void(0)"),
+                date: image.startdate,
+            })
+            .collect())
+    }
+}
+
+// NASA Astronomy Picture of the Day. With a count above one, APOD picks that many random
+// pictures from its archive instead of today's.
+pub struct Apod {
+    pub base_url: String,
+    pub api_key: String,
+    pub count: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+#[derive(Deserialize)]
+struct ApodEntry {
+    #[serde(default)]
+    title: String,
+    url: Option<String>,
+    hdurl: Option<String>,
+    #[serde(default)]
+    media_type: String,
+    copyright: Option<String>,
+    date: Option<String>,
+}
+
+impl RemoteProvider for Apod {
+    fn name(&self) -> &str {
+        "apod"
+    }
+
+    fn list(&self, http: &dyn HttpClient) -> Result<Vec<RemoteImage>, String> {
+        let mut url = format!("{}?api_key={}", self.base_url, self.api_key);
+        if self.count > 1 {
+            url.push_str(&format!("&count={}", self.count));
+        }
+
+        let entries = match get_json(http, &url)? {
+            OneOrMany::One(entry) => vec![entry],
+            OneOrMany::Many(entries) => entries,
+        };
+
+        // Some days are videos
+        Ok(entries
+            .into_iter()
+            .filter(|entry: &ApodEntry| entry.media_type == "image")
+            .filter_map(|entry| {
+                let url = entry.hdurl.or(entry.url)?;
+                let page_url = entry.date.as_deref().and_then(apod_page);
+                Some(RemoteImage {
+                    url,
+                    title: entry.title,
+                    attribution: entry
+                        .copyright
+                        .map(|c| c.trim().to_string())
+                        .unwrap_or_else(|| "NASA".to_string()),
+                    page_url,
+                    date: entry.date,
+                })
+            })
+            .collect())
+    }
+}
+
+// "2024-05-01" is described on https://apod.nasa.gov/apod/ap240501.html
+fn apod_page(date: &str) -> Option<String> {
+    let digits: String = date.chars().filter(|c| c.is_ascii_digit()).collect();
+    if digits.len() != 8 {
+        return None;
+    }
+    Some(format!(
+        "https://apod.nasa.gov/apod/ap{}.html",
+        &digits[2..]
+    ))
+}
+
+// A feed in the JSON Feed format (https://jsonfeed.org)
+pub struct JsonFeed {
+    pub url: String,
+    pub count: usize,
+}
+
+#[derive(Deserialize)]
+struct JsonFeedDocument {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    authors: Vec<JsonFeedAuthor>,
+    #[serde(default)]
+    items: Vec<JsonFeedItem>,
+}
+
+#[derive(Deserialize)]
+struct JsonFeedAuthor {
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JsonFeedItem {
+    url: Option<String>,
+    title: Option<String>,
+    image: Option<String>,
+    banner_image: Option<String>,
+    #[serde(default)]
+    attachments: Vec<JsonFeedAttachment>,
+    #[serde(default)]
+    authors: Vec<JsonFeedAuthor>,
+    date_published: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JsonFeedAttachment {
+    url: String,
+    #[serde(default)]
+    mime_type: String,
+}
+
+fn author_names(authors: &[JsonFeedAuthor]) -> String {
+    authors
+        .iter()
+        .filter_map(|author| author.name.as_deref())
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+impl RemoteProvider for JsonFeed {
+    fn name(&self) -> &str {
+        "json-feed"
+    }
+
+    fn list(&self, http: &dyn HttpClient) -> Result<Vec<RemoteImage>, String> {
+        let feed: JsonFeedDocument = get_json(http, &self.url)?;
+        let feed_authors = author_names(&feed.authors);
+
+        Ok(feed
+            .items
+            .into_iter()
+            .filter_map(|item| {
+                let attachment = item
+                    .attachments
+                    .iter()
+                    .find(|attachment| attachment.mime_type.starts_with("image/"))
+                    .map(|attachment| attachment.url.clone());
+                let url = attachment.or(item.image).or(item.banner_image)?;
+
+                let authors = author_names(&item.authors);
+                let attribution = [authors.as_str(), feed_authors.as_str(), feed.title.as_str()]
+                    .into_iter()
+                    .find(|name| !name.is_empty())
+                    .unwrap_or_default()
+                    .to_string();
+
+                Some(RemoteImage {
+                    url: absolute_url(&self.url, &url),
+                    title: item.title.unwrap_or_default(),
+                    attribution,
+                    page_url: item.url,
+                    date: item.date_published,
+                })
+            })
+            .take(self.count)
+            .collect())
+    }
+}
+
+// An RSS 2.0 feed with Media RSS content or image enclosures
+pub struct RssFeed {
+    pub url: String,
+    pub count: usize,
+}
+
+const MEDIA_NAMESPACE: &str = "http://search.yahoo.com/mrss/";
+const DUBLIN_CORE_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+
+impl RemoteProvider for RssFeed {
+    fn name(&self) -> &str {
+        "rss"
+    }
+
+    fn list(&self, http: &dyn HttpClient) -> Result<Vec<RemoteImage>, String> {
+        let bytes = http.get(&self.url)?;
+        let text = String::from_utf8_lossy(&bytes);
+        let document = match roxmltree::Document::parse(&text) {
+            Ok(document) => document,
+            Err(e) => return Err(format!("Unexpected response from '{}': {}", self.url, e)),
+        };
+
+        let channel = document
+            .descendants()
+            .find(|node| node.has_tag_name("channel"));
+        let channel_title = channel
+            .and_then(|channel| child_text(channel, "", "title"))
+            .unwrap_or_default();
+
+        Ok(document
+            .descendants()
+            .filter(|node| node.has_tag_name("item"))
+            .filter_map(|item| {
+                let url = rss_image_url(item)?;
+                let attribution = child_text(item, MEDIA_NAMESPACE, "credit")
+                    .or_else(|| child_text(item, DUBLIN_CORE_NAMESPACE, "creator"))
+                    .or_else(|| child_text(item, "", "author"))
+                    .unwrap_or_else(|| channel_title.clone());
+
+                Some(RemoteImage {
+                    url: absolute_url(&self.url, &url),
+                    title: child_text(item, "", "title").unwrap_or_default(),
+                    attribution,
+                    page_url: child_text(item, "", "link"),
+                    date: child_text(item, "", "pubDate"),
+                })
+            })
+            .take(self.count)
+            .collect())
+    }
+}
+
+fn is_element(node: &roxmltree::Node, namespace: &str, name: &str) -> bool {
+    node.is_element()
+        && node.tag_name().name() == name
+        && node.tag_name().namespace().unwrap_or("") == namespace
+}
+
+fn child_text(node: roxmltree::Node, namespace: &str, name: &str) -> Option<String> {
+    node.children()
+        .find(|child| is_element(child, namespace, name))
+        .and_then(|child| child.text())
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+// media:content may sit directly in the item or inside a media:group
+fn rss_image_url(item: roxmltree::Node) -> Option<String> {
+    let is_image = |node: &roxmltree::Node| {
+        node.attribute("medium") == Some("image")
+            || node
+                .attribute("type")
+                .is_some_and(|mime| mime.starts_with("image/"))
+    };
+
+    item.descendants()
+        .filter(|node| is_element(node, MEDIA_NAMESPACE, "content"))
+        .find(|node| is_image(node))
+        .or_else(|| {
+            item.children()
+                .filter(|node| is_element(node, "", "enclosure"))
+                .find(|node| is_image(node))
+        })
+        .and_then(|node| node.attribute("url"))
+        .map(|url| url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    // Serves canned responses and remembers what was asked for
+    #[derive(Default)]
+    struct StubClient {
+        responses: HashMap<String, Vec<u8>>,
+        requests: RefCell<Vec<String>>,
+    }
+
+    impl StubClient {
+        fn with(mut self, url: &str, body: impl Into<Vec<u8>>) -> StubClient {
+            self.responses.insert(url.to_string(), body.into());
+            self
+        }
+    }
+
+    impl HttpClient for StubClient {
+        fn get(&self, url: &str) -> Result<Vec<u8>, String> {
+            self.requests.borrow_mut().push(url.to_string());
+            self.responses
+                .get(url)
+                .cloned()
+                .ok_or_else(|| format!("Failed to fetch '{}': 404", url))
+        }
+    }
+
+    fn jpeg() -> Vec<u8> {
+        fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("test_resources/test_a.jpg")).unwrap()
+    }
+
+    #[test]
+    fn absolute_url_resolves_like_a_browser() {
+        let base = "https://example.com/feeds/photos.xml";
+        assert_eq!(
+            absolute_url(base, "images/a.jpg"),
+            "https://example.com/feeds/images/a.jpg"
+        );
+        assert_eq!(
+            absolute_url(base, "/a.jpg?x=1"),
+            "https://example.com/a.jpg?x=1"
+        );
+        assert_eq!(absolute_url(base, "../a.jpg"), "https://example.com/a.jpg");
+        assert_eq!(
+            absolute_url(base, "//cdn.example.net/a.jpg"),
+            "https://cdn.example.net/a.jpg"
+        );
+        assert_eq!(
+            absolute_url(base, "http://other.example/a.jpg"),
+            "http://other.example/a.jpg"
+        );
+    }
+
+    #[test]
+    fn bing_lists_its_archive() {
+        let http = StubClient::default().with(
+            "https://www.bing.com/HPImageArchive.aspx?format=js&idx=0&n=2&mkt=en-GB",
+            r#"{"images": [
+                {"url": "/th?id=OHR.Puffins_1920x1080.jpg&rf=LaDigue_1920x1080.jpg",
+                 "title": "Puffins", "copyright": "Puffins (© Jane Doe)",
+                 "copyrightlink": "https://www.bing.com/search?q=puffins",
+                 "startdate": "20240501"},
+                {"url": "/th?id=OHR.Dunes_1920x1080.jpg", "title": "Dunes",
+                 "copyright": "Dunes (© John Roe)", "copyrightlink": "javascript:void(0)"}
+            ]}"#,
+        );
+        let bing = Bing {
+            base_url: BING_URL.to_string(),
+            market: "en-GB".to_string(),
+            count: 2,
+        };
+
+        let images = bing.list(&http).unwrap();
+        assert_eq!(images.len(), 2);
+        assert_eq!(
+            images[0].url,
+            "https://www.bing.com/th?id=OHR.Puffins_1920x1080.jpg&rf=LaDigue_1920x1080.jpg"
+        );
+        assert_eq!(images[0].title, "Puffins");
+        assert_eq!(images[0].attribution, "Puffins (© Jane Doe)");
+        assert_eq!(
+            images[0].page_url.as_deref(),
+            Some("https://www.bing.com/search?q=puffins")
+        );
+        assert_eq!(images[0].date.as_deref(), Some("20240501"));
+        assert_eq!(images[1].page_url, None);
+    }
+
+    #[test]
+    fn apod_skips_videos_and_prefers_hd() {
+        let http = StubClient::default().with(
+            "https://api.nasa.gov/planetary/apod?api_key=KEY&count=3",
+            r#"[
+                {"title": "Nebula", "url": "https://apod.nasa.gov/a.jpg",
+                 "hdurl": "https://apod.nasa.gov/a_hd.jpg", "media_type": "image",
+                 "copyright": "\nJane Doe\n", "date": "2024-05-01"},
+                {"title": "Launch", "url": "https://youtube.com/embed/x",
+                 "media_type": "video", "date": "2024-05-02"},
+                {"title": "Moon", "url": "https://apod.nasa.gov/b.jpg",
+                 "media_type": "image", "date": "2024-05-03"}
+            ]"#,
+        );
+        let apod = Apod {
+            base_url: APOD_URL.to_string(),
+            api_key: "KEY".to_string(),
+            count: 3,
+        };
+
+        let images = apod.list(&http).unwrap();
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].url, "https://apod.nasa.gov/a_hd.jpg");
+        assert_eq!(images[0].attribution, "Jane Doe");
+        assert_eq!(
+            images[0].page_url.as_deref(),
+            Some("https://apod.nasa.gov/apod/ap240501.html")
+        );
+        assert_eq!(images[1].url, "https://apod.nasa.gov/b.jpg");
+        assert_eq!(images[1].attribution, "NASA");
+    }
+
+    #[test]
+    fn json_feed_finds_images_and_credits() {
+        let feed_url = "https://example.com/feeds/photos.json";
+        let http = StubClient::default().with(
+            feed_url,
+            r#"{"version": "https://jsonfeed.org/version/1.1", "title": "Photos",
+                "authors": [{"name": "Feed Author"}],
+                "items": [
+                    {"id": "1", "url": "https://example.com/1", "title": "One",
+                     "attachments": [
+                        {"url": "one.mp3", "mime_type": "audio/mpeg"},
+                        {"url": "images/one.jpg", "mime_type": "image/jpeg"}
+                     ],
+                     "authors": [{"name": "Item Author"}],
+                     "date_published": "2024-05-01T10:00:00Z"},
+                    {"id": "2", "title": "Two", "image": "https://cdn.example.net/two.png"},
+                    {"id": "3", "title": "No image"}
+                ]}"#,
+        );
+        let feed = JsonFeed {
+            url: feed_url.to_string(),
+            count: 10,
+        };
+
+        let images = feed.list(&http).unwrap();
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].url, "https://example.com/feeds/images/one.jpg");
+        assert_eq!(images[0].title, "One");
+        assert_eq!(images[0].attribution, "Item Author");
+        assert_eq!(images[0].page_url.as_deref(), Some("https://example.com/1"));
+        assert_eq!(images[0].date.as_deref(), Some("2024-05-01T10:00:00Z"));
+        assert_eq!(images[1].url, "https://cdn.example.net/two.png");
+        assert_eq!(images[1].attribution, "Feed Author");
+    }
+
+    #[test]
+    fn rss_finds_media_content_and_enclosures() {
+        let feed_url = "https://example.com/rss/photos.xml";
+        let http = StubClient::default().with(
+            feed_url,
+            r#"<?xml version="1.0"?>
+            <rss version="2.0" xmlns:media="http://search.yahoo.com/mrss/"
+                 xmlns:dc="http://purl.org/dc/elements/1.1/">
+              <channel>
+                <title>Photo Blog</title>
+                <item>
+                  <title>Grouped</title>
+                  <link>https://example.com/grouped</link>
+                  <media:group>
+                    <media:content url="/full/grouped.jpg" medium="image"/>
+                  </media:group>
+                  <media:credit>Jane Doe</media:credit>
+                  <pubDate>Wed, 01 May 2024 10:00:00 GMT</pubDate>
+                </item>
+                <item>
+                  <title>Enclosed</title>
+                  <enclosure url="enclosed.png" type="image/png" length="1"/>
+                  <dc:creator>John Roe</dc:creator>
+                </item>
+                <item>
+                  <title>Podcast</title>
+                  <enclosure url="episode.mp3" type="audio/mpeg" length="1"/>
+                </item>
+                <item>
+                  <title>Uncredited</title>
+                  <media:content url="https://cdn.example.net/u.jpg" type="image/jpeg"/>
+                </item>
+              </channel>
+            </rss>"#,
+        );
+        let feed = RssFeed {
+            url: feed_url.to_string(),
+            count: 10,
+        };
+
+        let images = feed.list(&http).unwrap();
+        let urls: Vec<&str> = images.iter().map(|image| image.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/full/grouped.jpg",
+                "https://example.com/rss/enclosed.png",
+                "https://cdn.example.net/u.jpg",
+            ]
+        );
+        let credits: Vec<&str> = images.iter().map(|i| i.attribution.as_str()).collect();
+        assert_eq!(credits, ["Jane Doe", "John Roe", "Photo Blog"]);
+        assert_eq!(
+            images[0].page_url.as_deref(),
+            Some("https://example.com/grouped")
+        );
+        assert_eq!(
+            images[0].date.as_deref(),
+            Some("Wed, 01 May 2024 10:00:00 GMT")
+        );
+    }
+
+    #[test]
+    fn fetch_downloads_each_url_once() {
+        let feed_url = "https://example.com/feed.json";
+        let http = StubClient::default()
+            .with(
+                feed_url,
+                r#"{"title": "Photos", "items": [
+                    {"title": "A", "image": "a.jpg"},
+                    {"title": "A again", "image": "https://example.com/a.jpg"},
+                    {"title": "Not an image", "image": "b.jpg"}
+                ]}"#,
+            )
+            .with("https://example.com/a.jpg", jpeg())
+            .with("https://example.com/b.jpg", "<html></html>");
+        let feed = JsonFeed {
+            url: feed_url.to_string(),
+            count: 10,
+        };
+        let dir = std::env::temp_dir().join(format!("background_remote_{}", std::process::id()));
+        let mut library = Library::default();
+
+        let added = fetch_into(&feed, &http, &mut library, &dir).unwrap();
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].source_url, "https://example.com/a.jpg");
+        assert_eq!(added[0].title, "A");
+        assert_eq!(added[0].attribution, "Photos");
+        assert_eq!(added[0].path, dir.join("a.jpg"));
+        assert!(added[0].path.exists());
+        assert_eq!(library.entries.len(), 1);
+
+        // Already in the library, only the feed and the rejected page are asked for again
+        http.requests.borrow_mut().clear();
+        let added = fetch_into(&feed, &http, &mut library, &dir).unwrap();
+        assert!(added.is_empty());
+        assert_eq!(
+            *http.requests.borrow(),
+            [feed_url, "https://example.com/b.jpg"]
+        );
+        assert_eq!(library.entries.len(), 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,440 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::config::{self, Config, Location, ScheduleRule};
+use crate::os_level::MonitorInfo;
+
+// When a rule starts: a clock time, or sunrise or sunset shifted by a number of seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeSpec {
+    At(NaiveTime),
+    Sunrise(i64),
+    Sunset(i64),
+}
+
+impl FromStr for TimeSpec {
+    type Err = String;
+
+    // Accepts "07:30", "sunrise", "sunset", or those two shifted like "sunset-30m" or "sunrise+1h"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(time) = NaiveTime::parse_from_str(s, "%H:%M") {
+            return Ok(TimeSpec::At(time));
+        }
+
+        let (event, offset) = match s.find(['+', '-']) {
+            Some(split) => s.split_at(split),
+            None => (s, ""),
+        };
+
+        let offset = match offset.split_at_checked(1) {
+            None => 0,
+            Some((sign, "")) => {
+                return Err(format!(
+                    "invalid time '{}', expected an amount after '{}' like 'sunset{}30m'",
+                    s, sign, sign
+                ))
+            }
+            Some((sign, amount)) => {
+                let seconds = config::parse_interval(amount)?.as_secs() as i64;
+                if sign == "-" {
+                    -seconds
+                } else {
+                    seconds
+                }
+            }
+        };
+
+        match event {
+            "sunrise" => Ok(TimeSpec::Sunrise(offset)),
+            "sunset" => Ok(TimeSpec::Sunset(offset)),
+            _ => Err(format!(
+                "invalid time '{}', expected something like '07:30', 'sunrise' or 'sunset-30m'",
+                s
+            )),
+        }
+    }
+}
+
+impl TimeSpec {
+    pub fn needs_location(&self) -> bool {
+        !matches!(self, TimeSpec::At(_))
+    }
+
+    // The local time this starts on the given day. None for sun based times without a location,
+    // or when the sun does not rise or set that day.
+    pub fn on(&self, date: NaiveDate, location: Option<Location>) -> Option<NaiveTime> {
+        let (event, offset) = match *self {
+            TimeSpec::At(time) => return Some(time),
+            TimeSpec::Sunrise(offset) => (sun_times(date, location?)?.0, offset),
+            TimeSpec::Sunset(offset) => (sun_times(date, location?)?.1, offset),
+        };
+
+        let local = event.with_timezone(&Local) + Duration::seconds(offset);
+        Some(local.time())
+    }
+}
+
+// Sunrise and sunset on a day, from the sunrise equation. Accurate to a minute or two, which is
+// plenty for switching wallpapers. None during polar day or night.
+pub fn sun_times(date: NaiveDate, location: Location) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let to_radians = PI / 180.0;
+
+    // Julian date of noon UTC on that day, counted from the J2000 epoch
+    let noon = date.and_hms_opt(12, 0, 0)?.and_utc().timestamp() as f64;
+    let days = noon / 86400.0 + 2440587.5 - 2451545.0 + 0.0008;
+
+    // Mean solar time at the longitude
+    let mean_solar = days - location.longitude / 360.0;
+
+    let (anomaly, ecliptic_longitude) = ecliptic(mean_solar);
+    let transit =
+        2451545.0 + mean_solar + 0.0053 * anomaly.sin() - 0.0069 * (2.0 * ecliptic_longitude).sin();
+
+    let declination = (ecliptic_longitude.sin() * (OBLIQUITY * to_radians).sin()).asin();
+    let latitude = location.latitude * to_radians;
+
+    // The sun's center is 0.833 degrees below the horizon at sunrise because of refraction
+    let hour_angle_cos = ((-0.833 * to_radians).sin() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+    if !(-1.0..=1.0).contains(&hour_angle_cos) {
+        return None;
+    }
+    let hour_angle = hour_angle_cos.acos() / to_radians;
+
+    let to_utc = |julian: f64| {
+        let seconds = (julian - 2440587.5) * 86400.0;
+        Utc.timestamp_opt(seconds.round() as i64, 0).single()
+    };
+
+    Some((
+        to_utc(transit - hour_angle / 360.0)?,
+        to_utc(transit + hour_angle / 360.0)?,
+    ))
+}
+
+// Where the sun is in the sky at a moment, as altitude above the horizon and azimuth clockwise
+// from north, both in degrees
+pub fn sun_position(time: DateTime<Utc>, location: Location) -> (f64, f64) {
+    let to_radians = PI / 180.0;
+    let days = time.timestamp() as f64 / 86400.0 + 2440587.5 - 2451545.0;
+
+    let (_, ecliptic_longitude) = ecliptic(days);
+    let obliquity = OBLIQUITY * to_radians;
+    let declination = (ecliptic_longitude.sin() * obliquity.sin()).asin();
+    let right_ascension =
+        (ecliptic_longitude.sin() * obliquity.cos()).atan2(ecliptic_longitude.cos());
+
+    let sidereal = (280.16 + 360.9856235 * days + location.longitude) * to_radians;
+    let hour_angle = sidereal - right_ascension;
+    let latitude = location.latitude * to_radians;
+
+    let altitude = (latitude.sin() * declination.sin()
+        + latitude.cos() * declination.cos() * hour_angle.cos())
+    .asin();
+    // Measured from south by the formula, turned around to count from north
+    let azimuth = hour_angle
+        .sin()
+        .atan2(hour_angle.cos() * latitude.sin() - declination.tan() * latitude.cos());
+
+    (
+        altitude / to_radians,
+        (azimuth / to_radians + 180.0).rem_euclid(360.0),
+    )
+}
+
+// Tilt of the earth's axis, in degrees
+const OBLIQUITY: f64 = 23.4397;
+
+// The sun's mean anomaly and ecliptic longitude in radians, some days after the J2000 epoch
+fn ecliptic(days: f64) -> (f64, f64) {
+    let to_radians = PI / 180.0;
+
+    let anomaly = (357.5291 + 0.98560028 * days).rem_euclid(360.0) * to_radians;
+    let center =
+        1.9148 * anomaly.sin() + 0.02 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic_longitude =
+        (anomaly / to_radians + center + 180.0 + 102.9372).rem_euclid(360.0) * to_radians;
+
+    (anomaly, ecliptic_longitude)
+}
+
+// A rule lasts from its start until the next rule for the same monitor starts, wrapping around
+// midnight. Returns the index of the rule active at the given time. Rules that can't start that
+// day are left out, config check names them with skipped_rules.
+pub fn active_rule(
+    rules: &[ScheduleRule],
+    location: Option<Location>,
+    monitor: &MonitorInfo,
+    now: NaiveDateTime,
+) -> Option<usize> {
+    let keys = config::monitor_keys(monitor);
+
+    let mut starts: Vec<(NaiveTime, usize)> = rules
+        .iter()
+        .enumerate()
+        .filter(|(_, rule)| match &rule.monitors {
+            Some(monitors) => monitors.iter().any(|key| keys.contains(key)),
+            None => true,
+        })
+        .filter_map(|(i, rule)| {
+            let spec: TimeSpec = rule.from.parse().ok()?;
+            Some((spec.on(now.date(), location)?, i))
+        })
+        .collect();
+    starts.sort();
+
+    starts
+        .iter()
+        .rev()
+        .find(|(start, _)| *start <= now.time())
+        .or(starts.last())
+        .map(|(_, i)| *i)
+}
+
+// Rules that active_rule leaves out on the given day, with the reason. Those are the ones whose
+// time doesn't parse or that follow the sun on a day it doesn't rise or set.
+pub fn skipped_rules(
+    rules: &[ScheduleRule],
+    location: Option<Location>,
+    date: NaiveDate,
+) -> Vec<String> {
+    let mut skipped = Vec::new();
+    for (i, rule) in rules.iter().enumerate() {
+        let spec: TimeSpec = match rule.from.parse() {
+            Ok(spec) => spec,
+            Err(e) => {
+                skipped.push(format!("schedule[{}].from: {}", i, e));
+                continue;
+            }
+        };
+        if spec.on(date, location).is_some() {
+            continue;
+        }
+
+        let reason = match location {
+            None => "there is no [location] to compute it for".to_string(),
+            Some(_) => format!("the sun doesn't rise and set on {}", date),
+        };
+        skipped.push(format!(
+            "schedule[{}].from: '{}' is skipped, {}",
+            i, rule.from, reason
+        ));
+    }
+    skipped
+}
+
+// What the schedule, a dynamic wallpaper or the system appearance changed on a monitor
+#[derive(Debug, Clone)]
+pub enum ScheduleChange {
+    // Other images should be shown
+    Sources(MonitorInfo),
+    // The image can stay, but has to be rendered again
+    Rendering(MonitorInfo),
+}
+
+// Remembers the sources and darkening every monitor had, to notice when the schedule, a dynamic
+// wallpaper or dark mode switches to others
+#[derive(Debug, Default)]
+pub struct ScheduleTracker {
+    profile: Option<String>,
+    monitors: BTreeMap<String, (Vec<PathBuf>, f64)>,
+}
+
+impl ScheduleTracker {
+    // What changed since the last call. Monitors seen for the first time are only remembered,
+    // and so is everything after the profile changed.
+    pub fn poll(&mut self, config: &Config, monitors: &[MonitorInfo]) -> Vec<ScheduleChange> {
+        let profile = config.matching_profile(monitors);
+        let profile_name = profile.map(|(name, _)| name.to_string());
+        let same_layout = self.profile == profile_name;
+        self.profile = profile_name;
+
+        let mut changes = Vec::new();
+        for monitor in monitors {
+            let settings = config.settings_for(monitor, profile.map(|(_, profile)| profile));
+            let current = (settings.sources, settings.darken);
+            let previous = self
+                .monitors
+                .insert(monitor.identity.clone(), current.clone());
+
+            match previous {
+                Some(previous) if !same_layout || previous == current => {}
+                Some((sources, _)) if sources != current.0 => {
+                    changes.push(ScheduleChange::Sources(monitor.clone()))
+                }
+                Some(_) => changes.push(ScheduleChange::Rendering(monitor.clone())),
+                None => {}
+            }
+        }
+
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os_level::Insets;
+
+    const LONDON: Location = Location {
+        latitude: 51.5074,
+        longitude: -0.1278,
+    };
+    const SYDNEY: Location = Location {
+        latitude: -33.8688,
+        longitude: 151.2093,
+    };
+    const TROMSO: Location = Location {
+        latitude: 69.6492,
+        longitude: 18.9553,
+    };
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        date(2024, 6, 21).and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    // Within a few minutes of the published times, which is all the equation promises
+    fn assert_near(actual: DateTime<Utc>, expected: &str) {
+        let difference = (actual - utc(expected)).num_seconds().abs();
+        assert!(
+            difference <= 180,
+            "{} is {}s away from {}",
+            actual,
+            difference,
+            expected
+        );
+    }
+
+    fn monitor(id: usize) -> MonitorInfo {
+        MonitorInfo {
+            name: format!("DP-{}", id),
+            identity: format!("identity-{}", id),
+            id,
+            width: 1920,
+            height: 1080,
+            insets: Insets::default(),
+        }
+    }
+
+    fn rule(from: &str, monitors: Option<&[&str]>) -> ScheduleRule {
+        ScheduleRule {
+            from: from.to_string(),
+            sources: vec!["folder".to_string()],
+            monitors: monitors.map(|keys| keys.iter().map(|key| key.to_string()).collect()),
+        }
+    }
+
+    #[test]
+    fn parses_clock_and_sun_times() {
+        let half_past_seven = NaiveTime::from_hms_opt(7, 30, 0).unwrap();
+        assert_eq!("07:30".parse(), Ok(TimeSpec::At(half_past_seven)));
+        assert_eq!("sunrise".parse(), Ok(TimeSpec::Sunrise(0)));
+        assert_eq!("sunset-30m".parse(), Ok(TimeSpec::Sunset(-1800)));
+        assert_eq!(" sunrise+1h ".parse(), Ok(TimeSpec::Sunrise(3600)));
+    }
+
+    #[test]
+    fn rejects_a_sign_without_an_amount() {
+        for text in ["sunrise-", "sunset+", "sunrise+ "] {
+            assert!(text.parse::<TimeSpec>().is_err(), "{} was accepted", text);
+        }
+    }
+
+    #[test]
+    fn rejects_other_times() {
+        for text in ["", "noon", "25:00", "sunset-30x", "moonrise+1h"] {
+            assert!(text.parse::<TimeSpec>().is_err(), "{} was accepted", text);
+        }
+    }
+
+    #[test]
+    fn sun_times_in_summer_and_winter() {
+        let (rise, set) = sun_times(date(2024, 6, 21), LONDON).unwrap();
+        assert_near(rise, "2024-06-21T03:43:00Z");
+        assert_near(set, "2024-06-21T20:21:00Z");
+
+        let (rise, set) = sun_times(date(2024, 12, 21), LONDON).unwrap();
+        assert_near(rise, "2024-12-21T08:04:00Z");
+        assert_near(set, "2024-12-21T15:53:00Z");
+    }
+
+    #[test]
+    fn sunrise_east_of_greenwich_is_the_utc_day_before() {
+        let (rise, set) = sun_times(date(2024, 6, 21), SYDNEY).unwrap();
+        assert_near(rise, "2024-06-20T21:00:00Z");
+        assert_near(set, "2024-06-21T06:54:00Z");
+    }
+
+    #[test]
+    fn no_sun_times_in_polar_day_or_night() {
+        assert_eq!(sun_times(date(2024, 6, 21), TROMSO), None);
+        assert_eq!(sun_times(date(2024, 12, 21), TROMSO), None);
+    }
+
+    #[test]
+    fn last_rule_of_the_day_lasts_past_midnight() {
+        let rules = [rule("07:00", None), rule("20:00", None)];
+        let monitor = monitor(1);
+        let active = |hour, minute| active_rule(&rules, None, &monitor, at(hour, minute));
+
+        assert_eq!(active(0, 0), Some(1));
+        assert_eq!(active(6, 59), Some(1));
+        assert_eq!(active(7, 0), Some(0));
+        assert_eq!(active(19, 59), Some(0));
+        assert_eq!(active(20, 0), Some(1));
+    }
+
+    #[test]
+    fn single_rule_is_always_active() {
+        let rules = [rule("22:00", None)];
+        assert_eq!(active_rule(&rules, None, &monitor(1), at(10, 0)), Some(0));
+    }
+
+    #[test]
+    fn rules_only_apply_to_their_monitors() {
+        let rules = [rule("07:00", None), rule("12:00", Some(&["2"]))];
+        assert_eq!(active_rule(&rules, None, &monitor(1), at(13, 0)), Some(0));
+        assert_eq!(active_rule(&rules, None, &monitor(2), at(13, 0)), Some(1));
+        assert_eq!(active_rule(&[], None, &monitor(1), at(13, 0)), None);
+    }
+
+    #[test]
+    fn sun_rules_are_left_out_when_the_sun_does_not_rise() {
+        let rules = [rule("sunrise", None), rule("12:00", None)];
+        let active = active_rule(&rules, Some(TROMSO), &monitor(1), at(8, 0));
+        assert_eq!(active, Some(1));
+        assert_eq!(active_rule(&rules, None, &monitor(1), at(8, 0)), Some(1));
+    }
+
+    #[test]
+    fn skipped_rules_are_named() {
+        let rules = [
+            rule("sunrise", None),
+            rule("07:00", None),
+            rule("sunset-", None),
+        ];
+        let skipped = skipped_rules(&rules, Some(TROMSO), date(2024, 6, 21));
+        assert_eq!(skipped.len(), 2);
+        assert!(skipped[0].starts_with("schedule[0].from:"));
+        assert!(skipped[0].contains("doesn't rise and set"));
+        assert!(skipped[1].starts_with("schedule[2].from:"));
+
+        assert!(skipped_rules(&rules[..2], Some(LONDON), date(2024, 6, 21)).is_empty());
+        let skipped = skipped_rules(&rules[..1], None, date(2024, 6, 21));
+        assert!(skipped[0].contains("[location]"));
+    }
+}
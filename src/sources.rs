@@ -0,0 +1,281 @@
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::decode;
+use crate::os_level::MonitorInfo;
+use crate::state;
+
+pub const IMAGE_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "bmp", "gif", "tif", "tiff", "webp", "avif", "heic", "heif", "svg",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Orientation {
+    Landscape,
+    Portrait,
+    Square,
+}
+
+impl Orientation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Orientation::Landscape => "Landscape",
+            Orientation::Portrait => "Portrait",
+            Orientation::Square => "Square",
+        }
+    }
+}
+
+pub fn is_image(path: &Path) -> bool {
+    match path.extension() {
+        Some(ext) => {
+            let ext = ext.to_string_lossy().to_lowercase();
+            IMAGE_EXTENSIONS.contains(&ext.as_str())
+        }
+        None => false,
+    }
+}
+
+// Expand every source into the image files it stands for. Folders are searched recursively.
+pub fn collect_images(sources: &[PathBuf]) -> Vec<PathBuf> {
+    let mut images = Vec::new();
+    for source in sources {
+        if source.is_dir() {
+            collect_from_dir(source, &mut images);
+        } else if source.is_file() {
+            images.push(source.clone());
+        } else {
+            eprintln!("Warning: Source '{}' does not exist", source.display());
+        }
+    }
+    images
+}
+
+fn collect_from_dir(dir: &Path, images: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Warning: Failed to read '{}': {}", dir.display(), e);
+            return;
+        }
+    };
+
+    let mut paths: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    paths.sort();
+
+    for path in paths {
+        if path.is_dir() {
+            collect_from_dir(&path, images);
+        } else if is_image(&path) {
+            images.push(path);
+        }
+    }
+}
+
+pub fn pick_random(images: &[PathBuf]) -> Option<PathBuf> {
+    images.choose(&mut rand::thread_rng()).cloned()
+}
+
+// Images with a higher weight are picked more often, those weighing nothing never
+pub fn pick_weighted(images: &[PathBuf], weight: &impl Fn(&Path) -> f64) -> Option<PathBuf> {
+    images
+        .choose_weighted(&mut rand::thread_rng(), |path| weight(path))
+        .ok()
+        .cloned()
+}
+
+// Reads only the image header, or the declared size of a drawing. Images within 5% of square
+// count as square.
+pub fn orientation(path: &Path) -> Option<Orientation> {
+    let (width, height) = decode::dimensions(path)?;
+    let aspect = width as f64 / height as f64;
+    if aspect > 1.05 {
+        Some(Orientation::Landscape)
+    } else if aspect < 1.0 / 1.05 {
+        Some(Orientation::Portrait)
+    } else {
+        Some(Orientation::Square)
+    }
+}
+
+// Sizes of images as decode::dimensions reports them, so each file is only opened again after it
+// changed. Stored as JSON in the user data directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SizeIndex {
+    #[serde(default)]
+    pub images: BTreeMap<PathBuf, SizedImage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SizedImage {
+    // Modification time of the file when it was measured, in seconds since the UNIX epoch.
+    pub modified: u64,
+    // Width and height the right way up, missing when the image could not be read.
+    pub size: Option<(usize, usize)>,
+}
+
+fn size_index_path() -> Result<PathBuf, String> {
+    Ok(state::data_dir()?.join("sizes.json"))
+}
+
+impl SizeIndex {
+    pub fn load() -> Result<SizeIndex, String> {
+        let path = size_index_path()?;
+        if !path.exists() {
+            return Ok(SizeIndex::default());
+        }
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => return Err(format!("Failed to read '{}': {}", path.display(), e)),
+        };
+
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse '{}': {}", path.display(), e))
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = size_index_path()?;
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize the size index: {}", e))?;
+
+        fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write '{}': {}", path.display(), e))
+    }
+
+    // Measures every image that is new or changed since it was measured. Returns how many were.
+    pub fn update(&mut self, images: &[PathBuf]) -> usize {
+        let mut measured = 0;
+        for path in images {
+            let modified = modified_secs(path);
+            if let Some(known) = self.images.get(path) {
+                if known.modified == modified {
+                    continue;
+                }
+            }
+
+            self.images.insert(
+                path.clone(),
+                SizedImage {
+                    modified,
+                    size: decode::dimensions(path),
+                },
+            );
+            measured += 1;
+        }
+        measured
+    }
+
+    pub fn size(&self, path: &Path) -> Option<(usize, usize)> {
+        self.images.get(path).and_then(|image| image.size)
+    }
+}
+
+fn modified_secs(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+// The sizes of the images, measuring only those the index doesn't know yet. The index is saved
+// on the way.
+fn measure(images: &[PathBuf]) -> SizeIndex {
+    let mut index = match SizeIndex::load() {
+        Ok(index) => index,
+        Err(e) => {
+            eprintln!("Warning: {}", e);
+            SizeIndex::default()
+        }
+    };
+
+    if index.update(images) > 0 {
+        if let Err(e) = index.save() {
+            eprintln!("Warning: {}", e);
+        }
+    }
+    index
+}
+
+// Fraction of the image that is cut off when it is scaled to fill a screen of the given size
+pub fn crop_fraction(image_size: (usize, usize), screen_size: (usize, usize)) -> f64 {
+    let image_aspect = image_size.0 as f64 / image_size.1 as f64;
+    let screen_aspect = screen_size.0 as f64 / screen_size.1 as f64;
+    1.0 - (image_aspect / screen_aspect).min(screen_aspect / image_aspect)
+}
+
+// Pick randomly, by weight, among the images whose shape suits the monitor. When none is within
+// the tolerance, the one needing the least cropping is used instead.
+pub fn pick_for_monitor(
+    images: &[PathBuf],
+    monitor: &MonitorInfo,
+    tolerance: f64,
+    weight: impl Fn(&Path) -> f64,
+) -> Option<PathBuf> {
+    let screen_size = (monitor.width, monitor.height);
+    let sizes = measure(images);
+
+    let scored: Vec<(f64, &PathBuf)> = images
+        .iter()
+        .filter_map(|path| {
+            sizes
+                .size(path)
+                .map(|size| (crop_fraction(size, screen_size), path))
+        })
+        .collect();
+
+    let good_matches: Vec<PathBuf> = scored
+        .iter()
+        .filter(|(crop, _)| *crop <= tolerance)
+        .map(|(_, path)| (*path).clone())
+        .collect();
+
+    if let Some(picked) = pick_weighted(&good_matches, &weight) {
+        return Some(picked);
+    }
+
+    scored
+        .iter()
+        .filter(|(_, path)| weight(path) > 0.0)
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, path)| (*path).clone())
+        .or_else(|| pick_weighted(images, &weight))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("test_resources")
+            .join(name)
+    }
+
+    #[test]
+    fn size_index_only_measures_new_or_changed_images() {
+        let images = [fixture("test_b.bmp"), fixture("test_g.svg")];
+        let mut index = SizeIndex::default();
+
+        assert_eq!(index.update(&images), 2);
+        assert_eq!(index.size(&images[0]), Some((16, 8)));
+        assert_eq!(index.size(&images[1]), Some((16, 8)));
+        assert_eq!(index.update(&images), 0);
+
+        // A different modification time means the file changed since
+        index.images.get_mut(&images[0]).unwrap().modified += 1;
+        assert_eq!(index.update(&images), 1);
+    }
+
+    #[test]
+    fn crop_fraction_of_matching_and_turned_shapes() {
+        assert_eq!(crop_fraction((1920, 1080), (3840, 2160)), 0.0);
+        assert!((crop_fraction((1080, 1920), (1920, 1080)) - 0.684).abs() < 0.001);
+    }
+}
@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::os_level::{self, MonitorInfo};
+
+// Everything the tool remembers between runs, stored as JSON in the user data directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    // Wallpapers that were set before the tool changed anything, one per monitor.
+    #[serde(default)]
+    pub original: Vec<SavedBackground>,
+    // The display layout seen the last time profiles were synced.
+    #[serde(default)]
+    pub layout: Vec<String>,
+    // The source image currently shown on each monitor.
+    #[serde(default)]
+    pub current: Vec<SavedBackground>,
+    // Source images shown on each monitor, oldest first, keyed by monitor identity.
+    #[serde(default)]
+    pub history: BTreeMap<String, Vec<PathBuf>>,
+}
+
+// How many images are kept per monitor for going back
+const HISTORY_LENGTH: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedBackground {
+    pub monitor: i32,
+    pub name: String,
+    #[serde(default)]
+    pub identity: String,
+    pub path: PathBuf,
+}
+
+pub fn data_dir() -> Result<PathBuf, String> {
+    let dir = match dirs::data_dir() {
+        Some(dir) => dir.join("background_manager"),
+        None => return Err("Could not determine the user data directory".to_string()),
+    };
+
+    if let Err(e) = fs::create_dir_all(&dir) {
+        return Err(format!("Failed to create '{}': {}", dir.display(), e));
+    }
+
+    Ok(dir)
+}
+
+fn state_path() -> Result<PathBuf, String> {
+    Ok(data_dir()?.join("state.json"))
+}
+
+impl State {
+    pub fn load() -> Result<State, String> {
+        let path = state_path()?;
+        if !path.exists() {
+            return Ok(State::default());
+        }
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => return Err(format!("Failed to read '{}': {}", path.display(), e)),
+        };
+
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Failed to parse '{}': {}", path.display(), e))
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = state_path()?;
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize state: {}", e))?;
+
+        fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write '{}': {}", path.display(), e))
+    }
+}
+
+// Saves a copy of the current wallpaper of every monitor, unless that was already done on an
+// earlier run. The files are copied because the OS may reuse or delete the originals.
+// Returns the backgrounds saved by this call.
+pub fn snapshot_original() -> Result<Vec<SavedBackground>, String> {
+    let mut state = State::load()?;
+    if !state.original.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let original_dir = data_dir()?.join("original");
+    if let Err(e) = fs::create_dir_all(&original_dir) {
+        return Err(format!(
+            "Failed to create '{}': {}",
+            original_dir.display(),
+            e
+        ));
+    }
+
+    for monitor in os_level::get_profile_info()? {
+        let desktop_num = monitor.id as i32;
+
+        let current = match os_level::get_background(desktop_num) {
+            Ok(path) => path,
+            Err(e) => {
+                eprintln!(
+                    "  Monitor {} - Could not read current background: {}",
+                    desktop_num, e
+                );
+                continue;
+            }
+        };
+
+        let file_name = match current.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => continue,
+        };
+
+        let saved_path = original_dir.join(format!("{}_{}", desktop_num, file_name));
+        if let Err(e) = fs::copy(&current, &saved_path) {
+            eprintln!(
+                "  Monitor {} - Failed to save original background '{}': {}",
+                desktop_num,
+                current.display(),
+                e
+            );
+            continue;
+        }
+
+        state.original.push(SavedBackground {
+            monitor: desktop_num,
+            name: monitor.name,
+            identity: monitor.identity,
+            path: saved_path,
+        });
+    }
+
+    state.save()?;
+    Ok(state.original)
+}
+
+pub fn record_current(monitor: &MonitorInfo, source: &Path) -> Result<(), String> {
+    let mut state = State::load()?;
+
+    state
+        .current
+        .retain(|saved| saved.identity != monitor.identity);
+    state.current.push(SavedBackground {
+        monitor: monitor.id as i32,
+        name: monitor.name.clone(),
+        identity: monitor.identity.clone(),
+        path: source.to_path_buf(),
+    });
+
+    let history = state.history.entry(monitor.identity.clone()).or_default();
+    if history.last().map(|last| last.as_path()) != Some(source) {
+        history.push(source.to_path_buf());
+    }
+    if history.len() > HISTORY_LENGTH {
+        history.drain(..history.len() - HISTORY_LENGTH);
+    }
+
+    state.save()
+}
+
+// Forget the image currently shown on a monitor and return the one shown before it
+pub fn pop_history(monitor: &MonitorInfo) -> Result<Option<PathBuf>, String> {
+    let mut state = State::load()?;
+
+    let previous = match state.history.get_mut(&monitor.identity) {
+        Some(history) if history.len() >= 2 => {
+            history.pop();
+            history.last().cloned()
+        }
+        _ => None,
+    };
+
+    state.save()?;
+    Ok(previous)
+}
+
+// The connected monitor a saved background belongs to, by identity or else by name
+pub fn find_monitor<'a>(
+    monitors: &'a [MonitorInfo],
+    saved: &SavedBackground,
+) -> Option<&'a MonitorInfo> {
+    monitors
+        .iter()
+        .find(|m| !saved.identity.is_empty() && m.identity == saved.identity)
+        .or_else(|| monitors.iter().find(|m| m.name == saved.name))
+}
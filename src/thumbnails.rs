@@ -0,0 +1,66 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::decode;
+use crate::image_proc;
+
+const THUMBNAIL_SIZE: u32 = 256;
+
+pub fn cache_dir() -> Result<PathBuf, String> {
+    let dir = match dirs::cache_dir() {
+        Some(dir) => dir.join("background_manager").join("thumbnails"),
+        None => return Err("Could not determine the user cache directory".to_string()),
+    };
+
+    if let Err(e) = fs::create_dir_all(&dir) {
+        return Err(format!("Failed to create '{}': {}", dir.display(), e));
+    }
+
+    Ok(dir)
+}
+
+// Named after the source path, size and modification time, so an edited image gets a new one
+fn thumbnail_path(source: &Path) -> Result<PathBuf, String> {
+    let metadata = match fs::metadata(source) {
+        Ok(metadata) => metadata,
+        Err(e) => return Err(format!("Failed to read '{}': {}", source.display(), e)),
+    };
+
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    metadata.len().hash(&mut hasher);
+    modified.hash(&mut hasher);
+
+    Ok(cache_dir()?.join(format!("{:016x}.png", hasher.finish())))
+}
+
+// Returns the cached thumbnail of an image, creating it first if needed
+pub fn get_or_create(source: &Path) -> Result<PathBuf, String> {
+    let path = thumbnail_path(source)?;
+    if path.exists() {
+        return Ok(path);
+    }
+
+    let img = decode::open(source, Some((THUMBNAIL_SIZE, THUMBNAIL_SIZE)))?;
+
+    let thumbnail = image_proc::fit_to_size(&img, (THUMBNAIL_SIZE, THUMBNAIL_SIZE));
+
+    match photon_rs::native::save_image(thumbnail, &path) {
+        Ok(_) => Ok(path),
+        Err(e) => Err(format!(
+            "Failed to save thumbnail of '{}': {}",
+            source.display(),
+            e
+        )),
+    }
+}
@@ -0,0 +1,213 @@
+use std::env;
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+use tray_item::{IconSource, TrayItem};
+
+use crate::config::Config;
+use crate::logic;
+use crate::os_level;
+use crate::schedule::ScheduleTracker;
+
+// How often the schedule and the system appearance are checked for changes
+const SCHEDULE_CHECK: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy)]
+enum TrayMessage {
+    // A monitor number, or None for all monitors
+    Next(Option<i32>),
+    Previous(Option<i32>),
+    Pause,
+    Resume,
+    OpenWindow,
+    #[cfg(target_os = "windows")]
+    Quit,
+}
+
+#[cfg(target_os = "windows")]
+fn tray_icon() -> IconSource {
+    // Embedded by build.rs from ui/icons/tray.rc
+    IconSource::Resource("tray-icon")
+}
+
+#[cfg(target_os = "macos")]
+fn tray_icon() -> IconSource {
+    IconSource::Data {
+        width: 64,
+        height: 64,
+        data: include_bytes!("../ui/icons/tray.png").to_vec(),
+    }
+}
+
+// Show the tray icon and keep rotating the backgrounds at the configured interval until the
+// user quits from the menu.
+pub fn run() -> Result<(), String> {
+    let monitors = os_level::get_profile_info()?;
+
+    let mut tray = TrayItem::new("Background Manager", tray_icon())
+        .map_err(|e| format!("Failed to create the tray icon: {}", e))?;
+    let (sender, receiver) = mpsc::channel();
+
+    add_item(&mut tray, "Next", &sender, TrayMessage::Next(None))?;
+    add_item(&mut tray, "Previous", &sender, TrayMessage::Previous(None))?;
+    add_item(&mut tray, "Pause rotation", &sender, TrayMessage::Pause)?;
+    add_item(&mut tray, "Resume rotation", &sender, TrayMessage::Resume)?;
+
+    // tray-item has no nested menus, so each monitor gets a labelled group of entries instead
+    for monitor in &monitors {
+        let desktop_num = monitor.id as i32;
+        add_label(
+            &mut tray,
+            &format!("Monitor {}: {}", desktop_num, monitor.name),
+        )?;
+        add_item(
+            &mut tray,
+            &format!("    Next on monitor {}", desktop_num),
+            &sender,
+            TrayMessage::Next(Some(desktop_num)),
+        )?;
+        add_item(
+            &mut tray,
+            &format!("    Previous on monitor {}", desktop_num),
+            &sender,
+            TrayMessage::Previous(Some(desktop_num)),
+        )?;
+    }
+
+    add_item(
+        &mut tray,
+        "Open Manager Window",
+        &sender,
+        TrayMessage::OpenWindow,
+    )?;
+
+    // The macOS menu only works while the application loop runs on the main thread
+    #[cfg(target_os = "macos")]
+    {
+        std::thread::spawn(move || control(receiver));
+        let inner = tray.inner_mut();
+        inner.add_quit_item("Quit");
+        inner.display();
+    }
+    #[cfg(target_os = "windows")]
+    {
+        add_item(&mut tray, "Quit", &sender, TrayMessage::Quit)?;
+        control(receiver);
+    }
+
+    Ok(())
+}
+
+fn add_label(tray: &mut TrayItem, label: &str) -> Result<(), String> {
+    tray.add_label(label)
+        .map_err(|e| format!("Failed to add '{}' to the tray menu: {}", label, e))
+}
+
+fn add_item(
+    tray: &mut TrayItem,
+    label: &str,
+    sender: &Sender<TrayMessage>,
+    message: TrayMessage,
+) -> Result<(), String> {
+    let sender = sender.clone();
+    tray.add_menu_item(label, move || {
+        let _ = sender.send(message);
+    })
+    .map_err(|e| format!("Failed to add '{}' to the tray menu: {}", label, e))
+}
+
+// Handle the menu entries and rotate the backgrounds whenever the interval runs out. The
+// schedule is followed in between, except while paused.
+fn control(receiver: Receiver<TrayMessage>) {
+    let mut paused = false;
+    let mut next_rotation = Instant::now() + rotation_interval();
+    let mut schedule = ScheduleTracker::default();
+    logic::check_schedule(&mut schedule);
+
+    loop {
+        let timeout = next_rotation
+            .saturating_duration_since(Instant::now())
+            .min(SCHEDULE_CHECK);
+        let message = match receiver.recv_timeout(timeout) {
+            Ok(message) => message,
+            Err(RecvTimeoutError::Timeout) if Instant::now() < next_rotation => {
+                if !paused {
+                    logic::check_schedule(&mut schedule);
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if !paused {
+                    logic::next_background(None);
+                }
+                next_rotation = Instant::now() + rotation_interval();
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+
+        match message {
+            TrayMessage::Next(monitor) => {
+                logic::next_background(monitor);
+                // Changing every monitor by hand counts as a rotation
+                if monitor.is_none() {
+                    next_rotation = Instant::now() + rotation_interval();
+                }
+            }
+            TrayMessage::Previous(monitor) => logic::previous_background(monitor),
+            TrayMessage::Pause => {
+                paused = true;
+                println!("Rotation paused");
+            }
+            TrayMessage::Resume => {
+                paused = false;
+                next_rotation = Instant::now() + rotation_interval();
+                println!("Rotation resumed");
+            }
+            TrayMessage::OpenWindow => open_manager_window(),
+            #[cfg(target_os = "windows")]
+            TrayMessage::Quit => return,
+        }
+    }
+}
+
+// Read again every time, the configuration may have been edited in the meantime
+fn rotation_interval() -> Duration {
+    match Config::load() {
+        Ok(config) => config.interval(),
+        Err(e) => {
+            eprintln!("Error: Invalid configuration {}", e);
+            Config::default().interval()
+        }
+    }
+}
+
+// The window is the `gui` command of this same executable
+fn open_manager_window() {
+    let program = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            eprintln!("Error: Failed to find the manager executable: {}", e);
+            return;
+        }
+    };
+
+    let mut command = Command::new(&program);
+    command.arg("gui");
+
+    // Don't open a console window next to the manager window
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    if let Err(e) = command.spawn() {
+        eprintln!(
+            "Error: Failed to open the manager window '{}': {}",
+            program.display(),
+            e
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use crate::os_level::{self, MonitorInfo};
+
+pub enum DisplayChange {
+    Added(MonitorInfo),
+    Resized(MonitorInfo),
+    Removed(MonitorInfo),
+}
+
+impl fmt::Display for DisplayChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DisplayChange::Added(m) => write!(
+                f,
+                "Monitor {} ({}) connected at {}x{}",
+                m.id, m.name, m.width, m.height
+            ),
+            DisplayChange::Resized(m) => write!(
+                f,
+                "Monitor {} ({}) changed resolution to {}x{}",
+                m.id, m.name, m.width, m.height
+            ),
+            DisplayChange::Removed(m) => write!(f, "Monitor {} ({}) disconnected", m.id, m.name),
+        }
+    }
+}
+
+// A burst of notifications comes with every change, one for each display involved
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+// Detects monitor changes by comparing what get_profile_info reports between calls. Where the
+// backend reports changes as they happen, wait returns as soon as one does. Elsewhere it
+// sleeps for the whole interval and the changes are only found by polling.
+pub struct DisplayWatcher {
+    known: Vec<MonitorInfo>,
+    events: Option<Receiver<()>>,
+}
+
+impl DisplayWatcher {
+    pub fn new() -> Result<DisplayWatcher, String> {
+        let (notify, events) = mpsc::channel();
+        let events = match os_level::watch_displays(notify) {
+            Ok(()) => Some(events),
+            Err(_) => None,
+        };
+
+        Ok(DisplayWatcher {
+            known: os_level::get_profile_info()?,
+            events,
+        })
+    }
+
+    // Whether the system tells about changes, or they are only found by polling
+    pub fn event_driven(&self) -> bool {
+        self.events.is_some()
+    }
+
+    // Block until the system reports a display change or the timeout passes, whichever is
+    // first. Either way poll tells what changed, if anything.
+    pub fn wait(&mut self, timeout: Duration) {
+        let Some(events) = &self.events else {
+            thread::sleep(timeout);
+            return;
+        };
+
+        match events.recv_timeout(timeout) {
+            Ok(()) => {
+                thread::sleep(SETTLE_TIME);
+                while events.try_recv().is_ok() {}
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                self.events = None;
+                thread::sleep(timeout);
+            }
+        }
+    }
+
+    pub fn monitors(&self) -> &[MonitorInfo] {
+        &self.known
+    }
+
+    pub fn poll(&mut self) -> Result<Vec<DisplayChange>, String> {
+        let current = os_level::get_profile_info()?;
+        let mut changes = Vec::new();
+
+        for monitor in &current {
+            match self.known.iter().find(|m| m.identity == monitor.identity) {
+                None => changes.push(DisplayChange::Added(monitor.clone())),
+                Some(known) if known.width != monitor.width || known.height != monitor.height => {
+                    changes.push(DisplayChange::Resized(monitor.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+
+        for known in &self.known {
+            if !current.iter().any(|m| m.identity == known.identity) {
+                changes.push(DisplayChange::Removed(known.clone()));
+            }
+        }
+
+        self.known = current;
+        Ok(changes)
+    }
+}